@@ -13,6 +13,7 @@ use once_cell::sync::Lazy;
 use sha2::{Digest, Sha256};
 use tempfile::NamedTempFile;
 
+use crate::config::Config;
 use crate::consts::VENV_BIN;
 use crate::platform::{get_app_dir, get_canonical_py_path, get_toolchain_python_bin};
 use crate::sources::{get_download_url, PythonVersion, PythonVersionRequest};
@@ -218,7 +219,7 @@ pub fn get_pip_module(venv: &Path) -> PathBuf {
     rv
 }
 
-fn check_hash(content: &[u8], hash: &'static str) -> Result<(), Error> {
+fn check_hash(content: &[u8], hash: &str) -> Result<(), Error> {
     let mut hasher = Sha256::new();
     hasher.update(content);
     let digest = hasher.finalize();
@@ -233,6 +234,17 @@ fn check_hash(content: &[u8], hash: &'static str) -> Result<(), Error> {
 pub fn fetch(
     version: &PythonVersionRequest,
     output: CommandOutput,
+) -> Result<PythonVersion, Error> {
+    fetch_with_mirror(version, Config::current().fetch_mirror().as_deref(), output)
+}
+
+/// Fetches a version, optionally downloading it from a mirror.
+///
+/// Files are looked up on the mirror as `{mirror}/{version}/{filename}`.
+pub fn fetch_with_mirror(
+    version: &PythonVersionRequest,
+    mirror: Option<&str>,
+    output: CommandOutput,
 ) -> Result<PythonVersion, Error> {
     if let Ok(version) = PythonVersion::try_from(version.clone()) {
         let py_bin = get_toolchain_python_bin(&version)?;
@@ -248,6 +260,17 @@ pub fn fetch(
         Some(result) => result,
         None => bail!("unknown version {}", version),
     };
+    let url = match mirror {
+        Some(mirror) => Cow::Owned(format!(
+            "{}/{}.{}.{}/{}",
+            mirror.trim_end_matches('/'),
+            version.major,
+            version.minor,
+            version.patch,
+            url.rsplit('/').next().unwrap_or(url)
+        )),
+        None => Cow::Borrowed(url),
+    };
 
     let target_dir = get_canonical_py_path(&version)?;
     let target_py_bin = get_toolchain_python_bin(&version)?;
@@ -270,7 +293,7 @@ pub fn fetch(
     if output != CommandOutput::Quiet {
         eprintln!("{} {}", style("Downloading").cyan(), version);
     }
-    let archive_buffer = download_url(url, output)?;
+    let archive_buffer = download_url(&url, output)?;
 
     if let Some(sha256) = sha256 {
         if output != CommandOutput::Quiet {
@@ -292,6 +315,47 @@ pub fn fetch(
     Ok(version)
 }
 
+/// Installs a version from a local archive instead of downloading it.
+pub fn fetch_from_archive(
+    archive: &Path,
+    version: &PythonVersion,
+    sha256: Option<&str>,
+    output: CommandOutput,
+) -> Result<(), Error> {
+    let target_dir = get_canonical_py_path(version)?;
+    if output == CommandOutput::Verbose {
+        eprintln!("target dir: {}", target_dir.display());
+    }
+    if target_dir.exists() {
+        bail!("toolchain {} is already installed", version);
+    }
+
+    let archive_buffer = fs::read(archive)
+        .with_context(|| format!("failed to read archive {}", archive.display()))?;
+    if let Some(sha256) = sha256 {
+        if output != CommandOutput::Quiet {
+            eprintln!("{}", style("Checking hash").cyan());
+        }
+        check_hash(&archive_buffer, &sha256.to_ascii_lowercase())
+            .with_context(|| format!("hash check of {} failed", archive.display()))?;
+    } else if output != CommandOutput::Quiet {
+        eprintln!("hash check skipped (no hash provided)");
+    }
+
+    fs::create_dir_all(&target_dir)
+        .with_context(|| format!("failed to create target folder {}", target_dir.display()))?;
+    if let Err(err) = unpack_archive(&archive_buffer, &target_dir, 1) {
+        fs::remove_dir_all(&target_dir).ok();
+        return Err(err).with_context(|| format!("unpacking of {} failed", archive.display()));
+    }
+
+    if output != CommandOutput::Quiet {
+        eprintln!("{} Installed {}", style("success:").green(), version);
+    }
+
+    Ok(())
+}
+
 pub fn download_url(url: &str, output: CommandOutput) -> Result<Vec<u8>, Error> {
     let mut archive_buffer = Vec::new();
     let mut handle = curl::easy::Easy::new();
@@ -5,15 +5,18 @@ use std::str::FromStr;
 
 use anyhow::{anyhow, bail, Context, Error};
 use clap::Parser;
-use pep440_rs::VersionSpecifiers;
-use pep508_rs::{Requirement, VersionOrUrl};
+use pep440_rs::{Version, VersionSpecifiers};
+use pep508_rs::{MarkerTree, Requirement, VersionOrUrl};
 use serde::Deserialize;
 use url::Url;
 
 use crate::bootstrap::ensure_self_venv;
 use crate::consts::VENV_BIN;
-use crate::pyproject::{DependencyKind, PyProject};
-use crate::utils::{format_requirement, CommandOutput};
+use crate::lock::{ExtraDependency, LockOptions};
+use crate::platform::get_index_netrc;
+use crate::pyproject::{DependencyKind, DependencyRef, PyProject};
+use crate::sync::{sync, SyncMode, SyncOptions};
+use crate::utils::{format_requirement, strip_url_credentials, CommandOutput};
 
 const PACKAGE_FINDER_SCRIPT: &str = r#"
 import sys
@@ -23,10 +26,11 @@ from packaging.version import Version
 
 py_ver = sys.argv[1]
 package = sys.argv[2]
-pre = len(sys.argv) > 3 and sys.argv[3] == "--pre"
+pre = "--pre" in sys.argv[3:]
+extra_index_urls = [x for x in sys.argv[3:] if x != "--pre"]
 
 finder = PackageFinder(
-    index_urls=["https://pypi.org/simple/"],
+    index_urls=["https://pypi.org/simple/"] + extra_index_urls,
 )
 if py_ver:
     finder.target_python.py_ver = tuple(map(int, py_ver.split('.')))
@@ -80,6 +84,9 @@ pub struct ReqExtras {
     /// Adds a dependency with a specific feature.
     #[arg(long)]
     features: Vec<String>,
+    /// The folder relative paths are interpolated against.
+    #[arg(skip)]
+    path_base: Option<PathBuf>,
 }
 
 impl ReqExtras {
@@ -87,6 +94,10 @@ impl ReqExtras {
         self.absolute = true;
     }
 
+    pub fn set_path_base(&mut self, base: &Path) {
+        self.path_base = Some(base.to_path_buf());
+    }
+
     pub fn apply_to_requirement(&self, req: &mut Requirement) -> Result<(), Error> {
         if let Some(ref git) = self.git {
             // XXX: today they are all aliases, it might be better to change
@@ -122,8 +133,9 @@ impl ReqExtras {
                 Url::from_file_path(env::current_dir()?.join(path))
                     .map_err(|_| anyhow!("unable to interpret '{}' as path", path.display()))?
             } else {
-                let base = env::current_dir()?;
-                let rv = pathdiff::diff_paths(base.join(path), &base).ok_or_else(|| {
+                let here = env::current_dir()?;
+                let base = self.path_base.as_deref().unwrap_or(&here);
+                let rv = pathdiff::diff_paths(here.join(path), base).ok_or_else(|| {
                     anyhow!(
                         "unable to create relative path from {} to {}",
                         base.display(),
@@ -164,9 +176,38 @@ pub struct Args {
     /// Add this to an optional dependency group.
     #[arg(long, conflicts_with = "dev", conflicts_with = "excluded")]
     optional: Option<String>,
+    /// Add this to a dependency group (`[dependency-groups]`).
+    #[arg(
+        long,
+        conflicts_with = "dev",
+        conflicts_with = "excluded",
+        conflicts_with = "optional"
+    )]
+    group: Option<String>,
     /// Include pre-releases when finding a package version.
     #[arg(long)]
     pre: bool,
+    /// Find the package on this extra index.
+    #[arg(long, conflicts_with = "excluded")]
+    index_url: Option<Url>,
+    /// Record the index in `tool.rye.sources` (without the credentials in the URL).
+    #[arg(long, requires = "index_url")]
+    record_source: bool,
+    /// Only install the dependency where this PEP 508 marker applies (e.g. "sys_platform == 'win32'").
+    #[arg(long)]
+    markers: Option<String>,
+    /// Install the local path dependency in editable mode.
+    #[arg(long, requires = "path", conflicts_with = "absolute")]
+    editable: bool,
+    /// Only update the lockfiles but do not install into the virtualenv.
+    #[arg(long)]
+    no_sync: bool,
+    /// Do not install the dev dependencies when syncing.
+    #[arg(long)]
+    no_dev: bool,
+    /// Only print the changes to the lockfiles without modifying any files.
+    #[arg(long, conflicts_with = "no_sync")]
+    dry_run: bool,
     /// Enables verbose diagnostics.
     #[arg(short, long)]
     verbose: bool,
@@ -175,7 +216,7 @@ pub struct Args {
     quiet: bool,
 }
 
-pub fn execute(cmd: Args) -> Result<(), Error> {
+pub fn execute(mut cmd: Args) -> Result<(), Error> {
     let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
     let mut python_path = ensure_self_venv(output).context("error bootstrapping venv")?;
     let mut added = Vec::new();
@@ -192,11 +233,50 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
     } else if cmd.excluded {
         DependencyKind::Excluded
     } else if let Some(ref section) = cmd.optional {
-        DependencyKind::Optional(section.into())
+        DependencyKind::Optional(section.clone().into())
+    } else if let Some(ref group) = cmd.group {
+        DependencyKind::Group(group.clone().into())
     } else {
         DependencyKind::Normal
     };
 
+    let markers = match cmd.markers {
+        Some(ref markers) => Some(MarkerTree::from_str(markers).map_err(|err| {
+            anyhow!(
+                "invalid marker '{}' (see https://peps.python.org/pep-0508/#environment-markers)\n{}",
+                markers,
+                err
+            )
+        })?),
+        None => None,
+    };
+    let extra_index_urls = match cmd.index_url {
+        Some(ref url) => {
+            let name = url
+                .host_str()
+                .ok_or_else(|| anyhow!("index url '{}' has no host", url))?;
+            if cmd.record_source {
+                // credentials belong into the credentials file
+                pyproject_toml.add_source(name, strip_url_credentials(url).as_str())?;
+            }
+            vec![url.to_string()]
+        }
+        None => Vec::new(),
+    };
+    let netrc = get_index_netrc(&extra_index_urls)?;
+    let finder = PackageFinder {
+        python_path: &python_path,
+        extra_index_urls: &extra_index_urls,
+        netrc: netrc.as_ref().map(|x| x.path()),
+        pre: cmd.pre,
+    };
+
+    if cmd.editable {
+        // ${PROJECT_ROOT} points to the workspace root
+        cmd.req_extras
+            .set_path_base(&pyproject_toml.workspace_path());
+    }
+
     for str_requirement in cmd.requirements {
         let mut requirement = Requirement::from_str(&str_requirement)?;
         cmd.req_extras.apply_to_requirement(&mut requirement)?;
@@ -204,9 +284,10 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
         // if we are excluding, we do not want a specific dependency version
         // stored, so we just skip the unearth step
         if !cmd.excluded {
-            let matches = find_best_matches(&python_path, Some(&py_ver), &requirement, cmd.pre)?;
+            let matches = finder.find_best_matches(Some(&py_ver), &requirement)?;
             if matches.is_empty() {
-                let all_matches = find_best_matches(&python_path, None, &requirement, cmd.pre)
+                let all_matches = finder
+                    .find_best_matches(None, &requirement)
                     .unwrap_or_default();
                 if all_matches.is_empty() {
                     bail!(
@@ -238,17 +319,89 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
 
             let m = matches.into_iter().next().unwrap();
             if requirement.version_or_url.is_none() {
+                let version = Version::from_str(&m.version).map_err(|msg| anyhow!("{}", msg))?;
+                // pre-releases are only considered by the resolver if the
+                // specifier itself mentions one, so fall back to the first
+                // alpha of the release.
+                let specifier = if version.any_prerelease() {
+                    if output != CommandOutput::Quiet {
+                        eprintln!(
+                            "warning: {} {} is a pre-release. Pre-release dependencies are \
+                             pinned in the lockfile and may need to be updated manually.",
+                            m.name, m.version
+                        );
+                    }
+                    format!(
+                        ">={}a0",
+                        version
+                            .release
+                            .iter()
+                            .map(|x| x.to_string())
+                            .collect::<Vec<_>>()
+                            .join(".")
+                    )
+                } else {
+                    format!("~={}", m.version)
+                };
                 requirement.version_or_url = Some(VersionOrUrl::VersionSpecifier(
-                    VersionSpecifiers::from_str(&format!("~={}", m.version))?,
+                    VersionSpecifiers::from_str(&specifier)?,
                 ));
             }
             requirement.name = m.name;
         }
 
+        if let Some(ref markers) = markers {
+            requirement.marker = Some(match requirement.marker {
+                Some(ref existing) => {
+                    MarkerTree::from_str(&format!("({}) and ({})", existing, markers))
+                        .map_err(|err| anyhow!("{}", err))?
+                }
+                None => markers.clone(),
+            });
+        }
         pyproject_toml.add_dependency(&requirement, &dep_kind)?;
+        if cmd.editable {
+            pyproject_toml.add_editable_dependency(&requirement.name)?;
+        }
         added.push(requirement);
     }
 
+    if cmd.dry_run {
+        // the pyproject.toml stays untouched, the added dependencies are
+        // handed to the lock step directly.
+        let project = pyproject_toml.normalized_name()?;
+        sync(SyncOptions {
+            output,
+            dev: !cmd.no_dev,
+            mode: SyncMode::LockOnly,
+            dry_run: true,
+            lock_options: LockOptions {
+                sources: extra_index_urls.clone(),
+                extra_dependencies: added
+                    .iter()
+                    .map(|req| ExtraDependency {
+                        project: project.clone(),
+                        kind: dep_kind.clone(),
+                        dep: DependencyRef::new(&format_requirement(req).to_string()),
+                    })
+                    .collect(),
+                ..LockOptions::default()
+            },
+            ..SyncOptions::default()
+        })
+        .context("failed to resolve dependencies")?;
+        if output != CommandOutput::Quiet {
+            for ref requirement in added {
+                println!(
+                    "Would add {} as {} dependency",
+                    format_requirement(requirement),
+                    &dep_kind
+                );
+            }
+        }
+        return Ok(());
+    }
+
     pyproject_toml.save()?;
 
     if output != CommandOutput::Quiet {
@@ -261,33 +414,62 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
         }
     }
 
+    sync(SyncOptions {
+        output,
+        dev: !cmd.no_dev,
+        mode: if cmd.no_sync {
+            SyncMode::LockOnly
+        } else {
+            SyncMode::Regular
+        },
+        lock_options: LockOptions {
+            sources: extra_index_urls,
+            ..LockOptions::default()
+        },
+        ..SyncOptions::default()
+    })
+    .context("failed to sync after adding dependencies")?;
+
     Ok(())
 }
 
-fn find_best_matches(
-    python_path: &PathBuf,
-    py_ver: Option<&str>,
-    requirement: &Requirement,
+/// Finds package versions with unearth.
+struct PackageFinder<'a> {
+    python_path: &'a Path,
+    extra_index_urls: &'a [String],
+    netrc: Option<&'a Path>,
     pre: bool,
-) -> Result<Vec<Match>, Error> {
-    let mut unearth = Command::new(python_path);
-    unearth
-        .arg("-c")
-        .arg(PACKAGE_FINDER_SCRIPT)
-        .arg(py_ver.unwrap_or(""))
-        .arg(&format_requirement(requirement).to_string());
-    if pre {
-        unearth.arg("--pre");
-    }
-    let unearth = unearth.stdout(Stdio::piped()).output()?;
-    if unearth.status.success() {
-        Ok(serde_json::from_slice(&unearth.stdout)?)
-    } else {
-        let log = String::from_utf8_lossy(&unearth.stderr);
-        bail!(
-            "failed to resolve package {}\n{}",
-            format_requirement(requirement),
-            log
-        );
+}
+
+impl PackageFinder<'_> {
+    fn find_best_matches(
+        &self,
+        py_ver: Option<&str>,
+        requirement: &Requirement,
+    ) -> Result<Vec<Match>, Error> {
+        let mut unearth = Command::new(self.python_path);
+        unearth
+            .arg("-c")
+            .arg(PACKAGE_FINDER_SCRIPT)
+            .arg(py_ver.unwrap_or(""))
+            .arg(&format_requirement(requirement).to_string())
+            .args(self.extra_index_urls);
+        if self.pre {
+            unearth.arg("--pre");
+        }
+        if let Some(netrc) = self.netrc {
+            unearth.env("NETRC", netrc);
+        }
+        let unearth = unearth.stdout(Stdio::piped()).output()?;
+        if unearth.status.success() {
+            Ok(serde_json::from_slice(&unearth.stdout)?)
+        } else {
+            let log = String::from_utf8_lossy(&unearth.stderr);
+            bail!(
+                "failed to resolve package {}\n{}",
+                format_requirement(requirement),
+                log
+            );
+        }
     }
 }
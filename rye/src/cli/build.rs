@@ -1,13 +1,34 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
+use std::{env, fs};
 
-use anyhow::{bail, Error};
+use anyhow::{bail, Context, Error};
 use clap::Parser;
 use console::style;
 
 use crate::bootstrap::ensure_self_venv;
 use crate::pyproject::{normalize_package_name, PyProject};
-use crate::utils::CommandOutput;
+use crate::utils::{get_venv_python_bin, CommandOutput};
+
+const METADATA_SCRIPT: &str = r#"
+import sys
+import build
+import build.env
+
+srcdir, outdir = sys.argv[1:3]
+config_settings = {}
+for setting in sys.argv[3:]:
+    key, _, value = setting.partition("=")
+    config_settings[key] = value
+
+with build.env.IsolatedEnvBuilder() as env:
+    builder = build.ProjectBuilder(
+        srcdir, python_executable=env.executable, scripts_dir=env.scripts_dir
+    )
+    env.install(builder.build_system_requires)
+    env.install(builder.get_requires_for_build("wheel", config_settings))
+    print(builder.metadata_path(outdir))
+"#;
 
 /// Builds a package for distribution.
 #[derive(Parser, Debug)]
@@ -18,6 +39,17 @@ pub struct Args {
     /// Build a wheel
     #[arg(long)]
     wheel: bool,
+    /// Only prepare the `.dist-info` metadata instead of building
+    #[arg(long, conflicts_with = "sdist", conflicts_with = "wheel")]
+    metadata_only: bool,
+    /// Print the `METADATA` of the wheel to stdout (written to `--out` if given).
+    #[arg(
+        long,
+        conflicts_with = "sdist",
+        conflicts_with = "wheel",
+        conflicts_with = "metadata_only"
+    )]
+    metadata: bool,
     /// Build all packages
     #[arg(short, long)]
     all: bool,
@@ -25,8 +57,29 @@ pub struct Args {
     #[arg(short, long)]
     package: Vec<String>,
     /// An output directory (defaults to `workspace/dist`)
-    #[arg(short, long)]
+    #[arg(short, long, visible_aliases = ["dist-dir", "outdir"])]
     out: Option<PathBuf>,
+    /// Pass a config setting to the build backend (e.g. `--config-setting=--features=foo`).
+    #[arg(short = 'C', long)]
+    config_setting: Vec<String>,
+    /// Remove old wheels and sdists from the output directory before building.
+    #[arg(long)]
+    clean: bool,
+    /// Only remove old wheels and sdists from the output directory.
+    #[arg(long, conflicts_with = "clean")]
+    clean_only: bool,
+    /// A Python script to run before building (overrides `tool.rye.build.pre-script`).
+    #[arg(long)]
+    pre_build_script: Option<PathBuf>,
+    /// Fail if the git working tree has uncommitted changes (see `tool.rye.build.require-clean-git`).
+    #[arg(long)]
+    require_clean_git: bool,
+    /// Build even if the git working tree has uncommitted changes.
+    #[arg(long, conflicts_with = "require_clean_git")]
+    allow_dirty: bool,
+    /// Build with the packages of the project's virtualenv instead of an isolated environment.
+    #[arg(long, conflicts_with = "metadata_only", conflicts_with = "metadata")]
+    no_isolation: bool,
     /// Enables verbose diagnostics.
     #[arg(short, long)]
     verbose: bool,
@@ -35,16 +88,31 @@ pub struct Args {
     quiet: bool,
 }
 
-pub fn execute(cmd: Args) -> Result<(), Error> {
+pub fn execute(mut cmd: Args) -> Result<(), Error> {
     let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
     let venv = ensure_self_venv(output)?;
     let project = PyProject::discover()?;
 
-    let out = match cmd.out {
-        Some(path) => path,
-        None => project.workspace_path().join("dist"),
+    // without an explicit output folder the metadata goes to stdout
+    let print_metadata = cmd.metadata && cmd.out.is_none();
+    let scratch = if print_metadata {
+        Some(tempfile::tempdir()?)
+    } else {
+        None
+    };
+    let out = match (cmd.out.take(), &scratch) {
+        (Some(path), _) => path,
+        (None, Some(scratch)) => scratch.path().to_path_buf(),
+        (None, None) => project.workspace_path().join("dist"),
     };
 
+    if cmd.clean || cmd.clean_only {
+        clean_dist_dir(&out, output)?;
+        if cmd.clean_only {
+            return Ok(());
+        }
+    }
+
     let mut projects = Vec::new();
 
     if cmd.all {
@@ -84,9 +152,51 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
             eprintln!("building {}", style(project.normalized_name()?).cyan());
         }
 
-        let mut build_cmd = Command::new(venv.join("bin/python"));
+        if !cmd.allow_dirty && (cmd.require_clean_git || project.require_clean_git()) {
+            ensure_clean_git(&project)?;
+        }
+
+        let pre_build_script = match cmd.pre_build_script {
+            Some(ref script) => Some(env::current_dir()?.join(script)),
+            None => project.pre_build_script(),
+        };
+        if let Some(script) = pre_build_script {
+            run_pre_build_script(&project, &script, &out, output)?;
+        }
+
+        if cmd.metadata_only || cmd.metadata {
+            let dist_info = prepare_metadata(&venv, &project, &out, &cmd.config_setting, output)?;
+            if print_metadata {
+                let metadata = dist_info.join("METADATA");
+                print!(
+                    "{}",
+                    fs::read_to_string(&metadata)
+                        .with_context(|| format!("failed to read {}", metadata.display()))?
+                );
+            } else if output != CommandOutput::Quiet {
+                eprintln!("Wrote metadata to {}", style(dist_info.display()).cyan());
+            }
+            continue;
+        }
+
+        let mut build_cmd = if cmd.no_isolation {
+            let python = get_venv_python_bin(&project.venv_path());
+            ensure_build_installed(&python)?;
+            if output != CommandOutput::Quiet {
+                eprintln!(
+                    "{} building without isolation, the result can differ from a build in a clean environment",
+                    style("warning:").yellow()
+                );
+            }
+            let mut build_cmd = Command::new(python);
+            build_cmd.arg("-mbuild").arg("--no-isolation");
+            build_cmd
+        } else {
+            let mut build_cmd = Command::new(venv.join("bin/python"));
+            build_cmd.arg("-mbuild");
+            build_cmd
+        };
         build_cmd
-            .arg("-mbuild")
             .env("NO_COLOR", "1")
             .arg("--outdir")
             .arg(&out)
@@ -98,6 +208,9 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
         if cmd.sdist {
             build_cmd.arg("--sdist");
         }
+        for setting in &cmd.config_setting {
+            build_cmd.arg(format!("--config-setting={}", setting));
+        }
 
         if output == CommandOutput::Quiet {
             build_cmd.stdout(Stdio::null());
@@ -110,5 +223,160 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
         }
     }
 
+    // the output folder goes to stdout so that scripts can pick it up
+    if !print_metadata {
+        println!("{}", env::current_dir()?.join(&out).display());
+    }
+
+    Ok(())
+}
+
+/// Prepares the `.dist-info` folder of a project in the output directory.
+///
+/// Returns the path to the `.dist-info` folder.
+fn prepare_metadata(
+    venv: &Path,
+    project: &PyProject,
+    out: &Path,
+    config_settings: &[String],
+    output: CommandOutput,
+) -> Result<PathBuf, Error> {
+    let mut metadata_cmd = Command::new(get_venv_python_bin(venv));
+    metadata_cmd
+        .arg("-c")
+        .arg(METADATA_SCRIPT)
+        .arg(&*project.root_path())
+        .arg(out)
+        .args(config_settings)
+        .stdout(Stdio::piped());
+    if output == CommandOutput::Quiet {
+        metadata_cmd.stderr(Stdio::null());
+    }
+    let rv = metadata_cmd
+        .output()
+        .context("unable to prepare metadata")?;
+    if !rv.status.success() {
+        bail!("failed to prepare metadata");
+    }
+    Ok(PathBuf::from(String::from_utf8_lossy(&rv.stdout).trim()))
+}
+
+/// Fails unless `build` can be imported by the given interpreter.
+///
+/// Non-isolated builds run the build frontend in the project's virtualenv,
+/// so it has to be installed there together with the build requirements.
+fn ensure_build_installed(python: &Path) -> Result<(), Error> {
+    if !python.is_file() {
+        bail!("project virtualenv does not exist, run `rye sync` to create it");
+    }
+    let status = Command::new(python)
+        .arg("-c")
+        .arg("import build")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .context("unable to run the virtualenv's python")?;
+    if !status.success() {
+        bail!(
+            "--no-isolation needs `build` in the virtualenv, install it with `rye add --dev build`"
+        );
+    }
+    Ok(())
+}
+
+/// Fails if there are uncommitted changes in the git working tree.
+///
+/// Changes to tracked files anywhere in the repository count, untracked files
+/// only if they are within the project.
+fn ensure_clean_git(project: &PyProject) -> Result<(), Error> {
+    let git_status = |args: &[&str]| -> Result<Vec<String>, Error> {
+        let rv = Command::new("git")
+            .arg("status")
+            .arg("--porcelain")
+            .args(args)
+            .current_dir(&*project.root_path())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .output()
+            .context("unable to run git")?;
+        if !rv.status.success() {
+            bail!(
+                "--require-clean-git needs a git repository, but {} is not inside one",
+                project.root_path().display()
+            );
+        }
+        Ok(String::from_utf8_lossy(&rv.stdout)
+            .lines()
+            .map(|x| x.to_string())
+            .collect())
+    };
+
+    let mut changes = git_status(&["--untracked-files=no"])?;
+    changes.extend(
+        git_status(&["--untracked-files=all", "--", "."])?
+            .into_iter()
+            .filter(|x| x.starts_with("??")),
+    );
+    if !changes.is_empty() {
+        eprintln!("Uncommitted changes:");
+        for change in &changes {
+            eprintln!("  {}", change);
+        }
+        bail!("git working tree is not clean (pass --allow-dirty to build anyway)");
+    }
+    Ok(())
+}
+
+/// Removes all wheels and sdists from the given output directory.
+fn clean_dist_dir(out: &Path, output: CommandOutput) -> Result<(), Error> {
+    let entries = match fs::read_dir(out) {
+        Ok(entries) => entries,
+        Err(_) => return Ok(()),
+    };
+    for entry in entries {
+        let path = entry?.path();
+        let filename = path.file_name().unwrap_or_default().to_string_lossy();
+        if path.is_file() && (filename.ends_with(".whl") || filename.ends_with(".tar.gz")) {
+            if output == CommandOutput::Verbose {
+                eprintln!("removing {}", path.display());
+            }
+            fs::remove_file(&path)
+                .with_context(|| format!("failed to remove {}", path.display()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Runs a pre-build script with the Python of the project's virtualenv.
+fn run_pre_build_script(
+    project: &PyProject,
+    script: &Path,
+    out: &Path,
+    output: CommandOutput,
+) -> Result<(), Error> {
+    let python = get_venv_python_bin(&project.venv_path());
+    if !python.is_file() {
+        bail!("project virtualenv does not exist, run `rye sync` to create it");
+    }
+    if output != CommandOutput::Quiet {
+        eprintln!(
+            "running pre-build script {}",
+            style(script.display()).cyan()
+        );
+    }
+    let mut script_cmd = Command::new(python);
+    script_cmd
+        .arg(script)
+        .env("RYES_BUILD_DIR", env::current_dir()?.join(out))
+        .current_dir(&*project.root_path());
+    if output == CommandOutput::Quiet {
+        script_cmd.stdout(Stdio::null());
+    }
+    let status = script_cmd
+        .status()
+        .with_context(|| format!("unable to run pre-build script {}", script.display()))?;
+    if !status.success() {
+        bail!("pre-build script {} failed", script.display());
+    }
     Ok(())
 }
@@ -0,0 +1,102 @@
+use std::io::Write;
+
+use age::secrecy::{ExposeSecret, Secret};
+use anyhow::{anyhow, Context, Error};
+use clap::Parser;
+use console::style;
+use tempfile::NamedTempFile;
+use toml_edit::Item;
+
+use crate::platform::{
+    credentials_path, decrypt, encrypt, escape_string, get_credentials, is_encrypted_token,
+};
+
+/// Manages the credentials stored by `rye publish`.
+#[derive(Parser, Debug)]
+pub struct Args {
+    #[command(subcommand)]
+    command: SubCommand,
+}
+
+/// Re-encrypts all stored tokens with a new passphrase.
+#[derive(Parser, Debug)]
+pub struct MigratePassphraseCommand {}
+
+#[derive(Parser, Debug)]
+enum SubCommand {
+    MigratePassphrase(MigratePassphraseCommand),
+}
+
+pub fn execute(cmd: Args) -> Result<(), Error> {
+    match cmd.command {
+        SubCommand::MigratePassphrase(_) => migrate_passphrase(),
+    }
+}
+
+fn migrate_passphrase() -> Result<(), Error> {
+    let mut credentials = get_credentials()?;
+
+    let old_phrase = prompt_passphrase("Enter the current passphrase (empty if none)", false)?;
+    let new_phrase = prompt_passphrase("Enter the new passphrase (empty for none)", true)?;
+
+    // all tokens are re-encrypted in memory first, so a token that cannot
+    // be decrypted leaves the credentials file untouched.
+    let mut count = 0;
+    for (repository, table) in credentials.iter_mut() {
+        let token = match table.get_mut("token") {
+            Some(token) => token,
+            None => continue,
+        };
+        let secret = Secret::new(escape_string(token.to_string()));
+        // plaintext tokens can be stored next to encrypted ones
+        let decrypted = if is_encrypted_token(secret.expose_secret()) {
+            decrypt(&secret, &old_phrase)
+                .with_context(|| format!("failed to decrypt token for '{}'", repository.get()))?
+        } else {
+            secret
+        };
+        let encrypted = if new_phrase.expose_secret().is_empty() {
+            decrypted.expose_secret().clone()
+        } else {
+            hex::encode(encrypt(&decrypted, new_phrase.clone())?)
+        };
+        *token = Item::Value(encrypted.into());
+        count += 1;
+    }
+
+    if count == 0 {
+        eprintln!("No stored tokens found");
+        return Ok(());
+    }
+
+    // write to a temporary file first and move it over the old one so that
+    // the credentials file is never left half written.
+    let path = credentials_path();
+    let dir = path
+        .parent()
+        .ok_or_else(|| anyhow!("invalid credentials path '{}'", path.display()))?;
+    let mut tmp = NamedTempFile::new_in(dir)?;
+    tmp.write_all(credentials.to_string().as_bytes())?;
+    tmp.persist(&path)
+        .context("unable to write to the credentials file")?;
+
+    eprintln!(
+        "{} re-encrypted {} token{}",
+        style("success:").green(),
+        count,
+        if count == 1 { "" } else { "s" }
+    );
+    Ok(())
+}
+
+fn prompt_passphrase(prompt: &str, confirm: bool) -> Result<Secret<String>, Error> {
+    let mut password = dialoguer::Password::new();
+    password
+        .with_prompt(prompt)
+        .allow_empty_password(true)
+        .report(false);
+    if confirm {
+        password.with_confirmation("Confirm the new passphrase", "passphrases do not match");
+    }
+    Ok(password.interact().map(Secret::new)?)
+}
@@ -0,0 +1,65 @@
+use std::env;
+
+use anyhow::{bail, Error};
+use clap::Parser;
+
+use crate::cli::run::venv_env_vars;
+use crate::pyproject::{PyProject, Script};
+
+/// Prints the environment variables `rye run` sets for the project.
+///
+/// Use `eval "$(rye env --export)"` to apply them to the current shell.
+#[derive(Parser, Debug)]
+pub struct Args {
+    /// Only print the value of this variable.
+    key: Option<String>,
+    /// Print the variables as `export KEY=VALUE` lines.
+    #[arg(long, conflicts_with = "key")]
+    export: bool,
+    /// Include the environment variables of this script.
+    #[arg(long)]
+    script: Option<String>,
+}
+
+pub fn execute(cmd: Args) -> Result<(), Error> {
+    let pyproject = PyProject::discover()?;
+    let mut vars = venv_env_vars(&pyproject, false)?
+        .into_iter()
+        .filter_map(|(key, value)| Some((key.to_string(), value?.to_string_lossy().into_owned())))
+        .collect::<Vec<_>>();
+    if let Some(ref script) = cmd.script {
+        match pyproject.get_script_cmd(script) {
+            Some(Script::Cmd(_, env_vars)) => {
+                let mut env_vars = env_vars.into_iter().collect::<Vec<_>>();
+                env_vars.sort();
+                for (key, value) in env_vars {
+                    vars.retain(|x| x.0 != key);
+                    vars.push((key, value));
+                }
+            }
+            Some(_) => {}
+            None => bail!("invalid or unknown script '{}'", script),
+        }
+    }
+
+    if let Some(key) = cmd.key {
+        match vars.iter().find(|x| x.0 == key) {
+            Some((_, value)) => println!("{}", value),
+            None => bail!("'{}' is not set by rye", key),
+        }
+    } else if cmd.export {
+        for (key, value) in &vars {
+            println!("export {}={}", key, shlex::quote(value));
+        }
+        // scripts are run without a PYTHONHOME
+        if env::var_os("PYTHONHOME").is_some() {
+            println!("unset PYTHONHOME");
+        }
+    } else {
+        for (key, value) in &vars {
+            println!("{}={}", key, value);
+        }
+    }
+
+    Ok(())
+}
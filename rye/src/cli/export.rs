@@ -0,0 +1,130 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Error};
+use clap::{Parser, ValueEnum};
+use pep508_rs::Requirement;
+
+use crate::bootstrap::download_url;
+use crate::lock::read_locked_requirements;
+use crate::pyproject::{normalize_package_name, PyProject};
+use crate::utils::{format_requirement, CommandOutput};
+
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+#[value(rename_all = "snake_case")]
+pub enum ExportFormat {
+    /// A plain `requirements.txt` file.
+    Requirements,
+    /// A conda `environment.yml` file.
+    Conda,
+}
+
+/// Exports the locked dependencies into another format.
+#[derive(Parser, Debug)]
+pub struct Args {
+    /// The format to export to.
+    #[arg(long, default_value = "requirements")]
+    format: ExportFormat,
+    /// The file to write to (defaults to stdout).
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// Export from the dev lockfile.
+    #[arg(long)]
+    dev: bool,
+    /// List packages available on conda-forge as conda dependencies.
+    #[arg(long)]
+    prefer_conda: bool,
+    /// Enables verbose diagnostics.
+    #[arg(short, long)]
+    verbose: bool,
+    /// Turns off all output.
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+}
+
+pub fn execute(cmd: Args) -> Result<(), Error> {
+    let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
+    let project = PyProject::discover()?;
+    let lockfile = project.lockfile_path(cmd.dev);
+    if !lockfile.is_file() {
+        bail!(
+            "lockfile '{}' does not exist, run `rye lock` first",
+            lockfile.display()
+        );
+    }
+    let requirements = read_locked_requirements(&lockfile)?;
+
+    let rv = match cmd.format {
+        ExportFormat::Requirements => {
+            let mut rv = String::new();
+            for req in &requirements {
+                writeln!(rv, "{}", format_requirement(req))?;
+            }
+            rv
+        }
+        ExportFormat::Conda => {
+            let python = project.venv_python_version()?;
+            let (conda, pip): (Vec<_>, Vec<_>) = requirements
+                .iter()
+                .partition(|req| cmd.prefer_conda && is_on_conda_forge(req, output));
+            render_conda_environment(
+                project.name().unwrap_or("rye"),
+                &format!("{}.{}", python.major, python.minor),
+                &conda,
+                &pip,
+            )?
+        }
+    };
+
+    match cmd.output {
+        Some(path) => fs::write(&path, rv)
+            .with_context(|| format!("failed to write export to {}", path.display()))?,
+        None => print!("{}", rv),
+    }
+
+    Ok(())
+}
+
+/// Checks if a package with the same name exists on conda-forge.
+fn is_on_conda_forge(req: &Requirement, output: CommandOutput) -> bool {
+    let name = normalize_package_name(&req.name);
+    let rv = download_url(
+        &format!("https://api.anaconda.org/package/conda-forge/{}", name),
+        CommandOutput::Quiet,
+    )
+    .is_ok();
+    if output == CommandOutput::Verbose {
+        eprintln!(
+            "{} {} available on conda-forge",
+            name,
+            if rv { "is" } else { "is not" }
+        );
+    }
+    rv
+}
+
+fn render_conda_environment(
+    name: &str,
+    python: &str,
+    conda: &[&Requirement],
+    pip: &[&Requirement],
+) -> Result<String, Error> {
+    let mut rv = String::new();
+    writeln!(rv, "name: {}", name)?;
+    writeln!(rv, "channels:")?;
+    writeln!(rv, "  - conda-forge")?;
+    writeln!(rv, "dependencies:")?;
+    writeln!(rv, "  - python={}", python)?;
+    for req in conda {
+        writeln!(rv, "  - {}", format_requirement(req))?;
+    }
+    if !pip.is_empty() {
+        writeln!(rv, "  - pip")?;
+        writeln!(rv, "  - pip:")?;
+        for req in pip {
+            writeln!(rv, "    - {}", format_requirement(req))?;
+        }
+    }
+    Ok(rv)
+}
@@ -1,14 +1,36 @@
-use anyhow::{Context, Error};
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Error};
 use clap::Parser;
+use once_cell::sync::Lazy;
+use regex::Regex;
 
-use crate::bootstrap::fetch;
+use crate::bootstrap::{fetch_from_archive, fetch_with_mirror};
+use crate::config::Config;
+use crate::sources::{PythonVersion, PythonVersionRequest};
 use crate::utils::CommandOutput;
 
+static ARCHIVE_VERSION_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^(cpython|pypy)-(\d+\.\d+\.\d+)").unwrap());
+
 /// Fetches a Python interpreter for the local machine.
 #[derive(Parser, Debug)]
 pub struct Args {
     /// The version of Python to fetch.
-    version: String,
+    #[arg(required_unless_present = "offline")]
+    version: Option<String>,
+    /// Download from this mirror instead (files are looked up as `{mirror}/{version}/{filename}`).
+    #[arg(long, conflicts_with = "offline")]
+    mirror: Option<String>,
+    /// Install from this local archive instead of downloading.
+    #[arg(long, value_name = "ARCHIVE")]
+    offline: Option<PathBuf>,
+    /// The expected SHA-256 hash of the local archive.
+    #[arg(long, requires = "offline")]
+    sha256: Option<String>,
+    /// The version of the local archive (inferred from the filename by default).
+    #[arg(long, requires = "offline", conflicts_with = "version")]
+    python_version: Option<String>,
     /// Enables verbose diagnostics.
     #[arg(short, long)]
     verbose: bool,
@@ -19,6 +41,30 @@ pub struct Args {
 
 pub fn execute(cmd: Args) -> Result<(), Error> {
     let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
-    fetch(&cmd.version.parse()?, output).context("error while fetching python installation")?;
+    if let Some(archive) = cmd.offline {
+        let version = match cmd.python_version.or(cmd.version) {
+            Some(version) => version,
+            None => {
+                let filename = archive.file_name().unwrap_or_default().to_string_lossy();
+                match ARCHIVE_VERSION_RE.captures(&filename) {
+                    Some(m) => format!("{}@{}", &m[1], &m[2]),
+                    None => bail!(
+                        "could not infer the Python version from '{}', pass --python-version",
+                        filename
+                    ),
+                }
+            }
+        };
+        let version = PythonVersion::try_from(version.parse::<PythonVersionRequest>()?)
+            .context("an exact Python version is required for offline installs")?;
+        fetch_from_archive(&archive, &version, cmd.sha256.as_deref(), output)
+            .context("error while installing python from archive")?;
+        return Ok(());
+    }
+
+    let mirror = cmd.mirror.or_else(|| Config::current().fetch_mirror());
+    let version = cmd.version.unwrap_or_default();
+    fetch_with_mirror(&version.parse()?, mirror.as_deref(), output)
+        .context("error while fetching python installation")?;
     Ok(())
 }
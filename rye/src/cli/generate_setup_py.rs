@@ -0,0 +1,46 @@
+use anyhow::{bail, Context, Error};
+use clap::Parser;
+use console::style;
+
+use crate::pyproject::PyProject;
+
+/// Generates a setup.py shim for tools that require one.
+///
+/// The shim does not carry any metadata itself, everything is read from
+/// the `pyproject.toml` file by setuptools.
+#[derive(Parser, Debug)]
+pub struct Args {
+    /// Overwrite an already existing setup.py.
+    #[arg(short, long)]
+    force: bool,
+}
+
+/// The setup.py shim.
+const SETUP_PY_TEMPLATE: &str = r#"# This setup.py only exists for tools that do not support pyproject.toml
+# based projects.  It is deprecated and will go away eventually, all the
+# metadata is read from pyproject.toml.
+#
+# Generated by `rye generate-setup-py`.
+import setuptools
+
+setuptools.setup()
+"#;
+
+pub fn execute(cmd: Args) -> Result<(), Error> {
+    let project = PyProject::discover()?;
+    let setup_py = project.root_path().join("setup.py");
+
+    if setup_py.is_file() && !cmd.force {
+        bail!("setup.py already exists. Use --force to overwrite it.");
+    }
+
+    std::fs::write(&setup_py, SETUP_PY_TEMPLATE).context("failed to write setup.py")?;
+
+    eprintln!(
+        "{} Wrote {}",
+        style("success:").green(),
+        style(setup_py.display()).cyan()
+    );
+
+    Ok(())
+}
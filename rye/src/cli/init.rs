@@ -1,19 +1,27 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use std::{env, fs};
 
 use anyhow::{bail, Context, Error};
 use clap::{Parser, ValueEnum};
 use console::style;
+use dialoguer::{Completion, Input, Select};
 use license::License;
 use minijinja::{context, Environment};
 use serde::Serialize;
 
+use crate::bootstrap::{ensure_self_venv, fetch};
 use crate::config::Config;
-use crate::platform::{get_default_author, get_latest_cpython, get_python_version_from_pyenv_pin};
-use crate::utils::is_inside_git_work_tree;
+use crate::consts::VENV_BIN;
+use crate::platform::{
+    get_default_author, get_latest_cpython, get_python_version_from_pyenv_pin,
+    list_known_toolchains,
+};
+use crate::sources::{matches_version, PythonVersionRequest};
+use crate::sync::{sync, SyncMode, SyncOptions};
+use crate::utils::{get_venv_python_bin, is_inside_git_work_tree, CommandOutput};
 
-#[derive(ValueEnum, Copy, Clone, Serialize, Debug)]
+#[derive(ValueEnum, Copy, Clone, PartialEq, Serialize, Debug)]
 #[value(rename_all = "snake_case")]
 #[serde(rename_all = "snake_case")]
 pub enum BuildSystem {
@@ -31,8 +39,8 @@ pub struct Args {
     /// Minimal Python version supported by this project.
     #[arg(long)]
     min_py: Option<String>,
-    /// Python version to use for the virtualenv.
-    #[arg(short, long)]
+    /// Python version to pin and use for the virtualenv (fetched if missing).
+    #[arg(short, long, visible_alias = "python")]
     py: Option<String>,
     /// Do not create a readme.
     #[arg(long)]
@@ -46,6 +54,36 @@ pub struct Args {
     /// The name of the package.
     #[arg(long)]
     name: Option<String>,
+    /// A short description of the project.
+    #[arg(long)]
+    description: Option<String>,
+    /// Place the package at the project root instead of in `src/`.
+    #[arg(long)]
+    flat_layout: bool,
+    /// Set up the project for Jupyter notebooks.
+    #[arg(long)]
+    jupyter: bool,
+    /// Scaffold the project from a Cookiecutter or Copier template (path or git URL).
+    #[arg(long)]
+    template: Option<String>,
+    /// Pass a variable to the template (`key=value`, can be supplied multiple times).
+    #[arg(long, requires = "template")]
+    extra: Vec<String>,
+    /// Enables verbose diagnostics.
+    #[arg(short, long)]
+    verbose: bool,
+    /// Turns off all output.
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+    /// The name of the author (defaults to the git user.name, pass an empty name for none).
+    #[arg(long)]
+    author: Option<String>,
+    /// The email of the author (defaults to the git user.email).
+    #[arg(long)]
+    email: Option<String>,
+    /// Interactively prompt for the project settings.
+    #[arg(short, long, conflicts_with = "template")]
+    interactive: bool,
 }
 
 /// The pyproject.toml template
@@ -54,10 +92,10 @@ pub struct Args {
 const TOML_TEMPLATE: &str = r#"[project]
 name = {{ name }}
 version = {{ version }}
-description = "Add a short description here"
+description = {{ description }}
 {%- if author %}
 authors = [
-    { name = {{ author[0] }}, email = {{ author[1] }} }
+    { name = {{ author[0] }}{% if author[1] %}, email = {{ author[1] }}{% endif %} }
 ]
 {%- endif %}
 dependencies = []
@@ -80,9 +118,24 @@ build-backend = "setuptools.build_meta"
 requires = ["flit_core>=3.4"]
 build-backend = "filt_core.buildapi"
 {%- endif %}
+{%- if flat_layout and build_system == "hatchling" %}
+
+[tool.hatch.build.targets.wheel]
+packages = [{{ module_name }}]
+{%- elif flat_layout and build_system == "setuptools" %}
+
+[tool.setuptools.packages.find]
+include = [{{ module_name ~ "*" }}]
+{%- endif %}
 
 [tool.rye]
 managed = true
+{%- if jupyter %}
+dev-dependencies = ["jupyter", "ipykernel", "nbstripout"]
+
+[tool.rye.scripts]
+notebook = "jupyter lab --notebook-dir notebooks"
+{%- endif %}
 
 "#;
 
@@ -118,13 +171,51 @@ wheels/
 
 # venv
 .venv
+{%- if jupyter %}
 
+# jupyter
+.ipynb_checkpoints/
+{%- endif %}
+
+"#;
+
+/// Template for the .gitattributes file of jupyter projects
+const GITATTRIBUTES_TEMPLATE: &str = r#"*.ipynb filter=nbstripout
+*.ipynb diff=ipynb
+
+"#;
+
+/// The sample notebook for jupyter projects
+const NOTEBOOK_TEMPLATE: &str = r#"{
+ "cells": [
+  {
+   "cell_type": "code",
+   "execution_count": null,
+   "metadata": {},
+   "outputs": [],
+   "source": [
+    "from {{ module_name }} import hello\n",
+    "\n",
+    "hello()"
+   ]
+  }
+ ],
+ "metadata": {
+  "kernelspec": {
+   "display_name": "Python 3",
+   "language": "python",
+   "name": "python3"
+  }
+ },
+ "nbformat": 4,
+ "nbformat_minor": 5
+}
 "#;
 
-pub fn execute(cmd: Args) -> Result<(), Error> {
+pub fn execute(mut cmd: Args) -> Result<(), Error> {
     let cfg = Config::current();
     let env = Environment::new();
-    let dir = env::current_dir()?.join(cmd.path);
+    let dir = env::current_dir()?.join(&cmd.path);
     let toml = dir.join("pyproject.toml");
     let readme = dir.join("README.md");
     let license_file = dir.join("LICENSE.txt");
@@ -134,24 +225,56 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
         bail!("pyproject.toml already exists");
     }
 
+    if let Some(ref template) = cmd.template {
+        let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
+        let name = slug::slugify(
+            cmd.name
+                .unwrap_or_else(|| dir.file_name().unwrap().to_string_lossy().into_owned()),
+        );
+        return init_from_template(template, &dir, &name, &cmd.extra, output);
+    }
+
+    if cmd.interactive {
+        prompt_for_settings(&mut cmd, &dir)?;
+    }
+    let py_requested = cmd.py.is_some();
+
+    // an explicitly requested version is also the minimum one and has to be
+    // installed before the virtualenv can be created.
+    let py_req = match cmd.py {
+        Some(ref py) if py_requested => {
+            let req: PythonVersionRequest = py
+                .parse()
+                .with_context(|| format!("invalid python version '{}'", py))?;
+            let installed = list_known_toolchains()?
+                .iter()
+                .any(|(ver, path)| path.is_file() && matches_version(&req, ver));
+            if !installed {
+                let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
+                fetch(&req, output)?;
+            }
+            Some(req)
+        }
+        _ => None,
+    };
+
     // fail silently if it already exists or cannot be created.
     fs::create_dir_all(&dir).ok();
 
     // Write pyproject.toml
-    let requires_python = match cmd.min_py {
-        Some(py) => format!(">= {}", py),
-        None => get_python_version_from_pyenv_pin()
+    let requires_python = match (cmd.min_py, py_req) {
+        (Some(py), _) => format!(">= {}", py),
+        (None, Some(req)) => match req.minor {
+            Some(minor) => format!(">= {}.{}", req.major, minor),
+            None => format!(">= {}", req.major),
+        },
+        (None, None) => get_python_version_from_pyenv_pin()
             .map(|x| format!(">= {}.{}", x.major, x.minor))
             .unwrap_or_else(|| cfg.default_requires_python()),
     };
     let py = match cmd.py {
         Some(py) => py,
-        None => {
-            let version = get_python_version_from_pyenv_pin()
-                .map(Ok)
-                .unwrap_or_else(get_latest_cpython)?;
-            format!("{}.{}.{}", version.major, version.minor, version.patch)
-        }
+        None => default_python_version()?,
     };
 
     let name = slug::slugify(
@@ -159,7 +282,22 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
             .unwrap_or_else(|| dir.file_name().unwrap().to_string_lossy().into_owned()),
     );
     let version = "0.1.0";
-    let author = get_default_author();
+    let description = cmd
+        .description
+        .unwrap_or_else(|| "Add a short description here".into());
+    // explicitly passed author info wins over what git has configured, an
+    // empty name records no author at all
+    let author = match (cmd.author, get_default_author()) {
+        (Some(name), _) if name.is_empty() => None,
+        (Some(name), default) => Some((
+            name,
+            cmd.email
+                .or_else(|| default.map(|x| x.1))
+                .unwrap_or_default(),
+        )),
+        (None, Some((name, email))) => Some((name, cmd.email.unwrap_or(email))),
+        (None, None) => None,
+    };
     let license = if let Some(license) = cmd.license {
         if !license_file.is_file() {
             let license_obj: &dyn License = license
@@ -181,7 +319,7 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
     };
 
     // write .python-version
-    if !python_version_file.is_file() {
+    if py_requested || !python_version_file.is_file() {
         fs::write(python_version_file, format!("{}\n", py))
             .context("could not write .python-version file")?;
     }
@@ -210,23 +348,46 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
         context! {
             name,
             version,
+            description,
             author,
             requires_python,
             license,
             with_readme,
+            jupyter => cmd.jupyter,
             build_system => cmd.build_system,
+            flat_layout => cmd.flat_layout,
+            module_name => name.replace('-', "_"),
         },
     )?;
     fs::write(&toml, rv).context("failed to write pyproject.toml")?;
 
-    let src_dir = dir.join("src");
+    // with a flat layout the package lives right next to the pyproject.toml
+    let module_name = name.replace('-', "_");
+    let (src_dir, project_dir) = if cmd.flat_layout {
+        (dir.join(&module_name), dir.join(&module_name))
+    } else {
+        (dir.join("src"), dir.join("src").join(&module_name))
+    };
     if !src_dir.is_dir() {
-        let project_dir = src_dir.join(name.replace('-', "_"));
         fs::create_dir_all(&project_dir).ok();
         let rv = env.render_named_str("__init__.py", INIT_PY_TEMPLATE, context! { name })?;
         fs::write(project_dir.join("__init__.py"), rv).context("failed to write __init__.py")?;
     }
 
+    if cmd.jupyter {
+        let notebooks_dir = dir.join("notebooks");
+        if !notebooks_dir.is_dir() {
+            fs::create_dir_all(&notebooks_dir).context("failed to create notebooks folder")?;
+            let rv = env.render_named_str(
+                "example.ipynb",
+                NOTEBOOK_TEMPLATE,
+                context! { module_name => name.replace('-', "_") },
+            )?;
+            fs::write(notebooks_dir.join("example.ipynb"), rv)
+                .context("failed to write example notebook")?;
+        }
+    }
+
     // if git init is successful prepare the local git repository
     if !is_inside_git_work_tree(&dir)
         && Command::new("git")
@@ -242,11 +403,46 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
 
         // create a .gitignore if one is missing
         if !gitignore.is_file() {
-            let rv = env.render_named_str("gitignore.txt", GITIGNORE_TEMPLATE, ())?;
+            let rv = env.render_named_str(
+                "gitignore.txt",
+                GITIGNORE_TEMPLATE,
+                context! { jupyter => cmd.jupyter },
+            )?;
             fs::write(&gitignore, rv).context("failed to write .gitignore")?;
         }
     }
 
+    // strip notebook outputs with nbstripout from the virtualenv on commit
+    if cmd.jupyter && is_inside_git_work_tree(&dir) {
+        let gitattributes = dir.join(".gitattributes");
+        if !gitattributes.is_file() {
+            fs::write(&gitattributes, GITATTRIBUTES_TEMPLATE)
+                .context("failed to write .gitattributes")?;
+        }
+        let python = Path::new(".venv").join(VENV_BIN).join("python");
+        for (key, value) in [
+            (
+                "filter.nbstripout.clean",
+                format!("\"{}\" -m nbstripout", python.display()),
+            ),
+            ("filter.nbstripout.smudge", "cat".to_string()),
+            (
+                "diff.ipynb.textconv",
+                format!("\"{}\" -m nbstripout -t", python.display()),
+            ),
+        ] {
+            Command::new("git")
+                .arg("config")
+                .arg(key)
+                .arg(value)
+                .current_dir(&dir)
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .ok();
+        }
+    }
+
     eprintln!(
         "{} Initialized project in {}",
         style("success:").green(),
@@ -256,3 +452,269 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
 
     Ok(())
 }
+
+/// Returns the Python version to pin if none was requested.
+fn default_python_version() -> Result<String, Error> {
+    let version = get_python_version_from_pyenv_pin()
+        .map(Ok)
+        .unwrap_or_else(get_latest_cpython)?;
+    Ok(format!(
+        "{}.{}.{}",
+        version.major, version.minor, version.patch
+    ))
+}
+
+/// Completes Python versions from the installed toolchains.
+struct ToolchainCompletion {
+    versions: Vec<String>,
+}
+
+impl Completion for ToolchainCompletion {
+    fn get(&self, input: &str) -> Option<String> {
+        self.versions.iter().find(|x| x.starts_with(input)).cloned()
+    }
+}
+
+/// Asks for all settings that were not passed on the command line.
+fn prompt_for_settings(cmd: &mut Args, dir: &Path) -> Result<(), Error> {
+    let name: String = Input::new()
+        .with_prompt("Project name")
+        .default(
+            cmd.name
+                .take()
+                .unwrap_or_else(|| slug::slugify(dir.file_name().unwrap().to_string_lossy())),
+        )
+        .interact_text()?;
+    cmd.name = Some(name);
+
+    let completion = ToolchainCompletion {
+        versions: list_known_toolchains()?
+            .into_iter()
+            .map(|(version, _)| format!("{}.{}.{}", version.major, version.minor, version.patch))
+            .collect(),
+    };
+    let was_requested = cmd.py.is_some();
+    let default_py = match cmd.py.take() {
+        Some(py) => py,
+        None => default_python_version()?,
+    };
+    let py: String = Input::new()
+        .with_prompt("Python version")
+        .default(default_py.clone())
+        .completion_with(&completion)
+        .interact_text()?;
+    // accepting the default keeps the version unrequested like on the
+    // command line, anything else is treated as if it was passed with --py
+    if was_requested || py != default_py {
+        cmd.py = Some(py);
+    }
+
+    let build_systems = BuildSystem::value_variants();
+    let idx = Select::new()
+        .with_prompt("Build system")
+        .items(
+            &build_systems
+                .iter()
+                .filter_map(|x| x.to_possible_value())
+                .map(|x| x.get_name().to_string())
+                .collect::<Vec<_>>(),
+        )
+        .default(
+            build_systems
+                .iter()
+                .position(|x| *x == cmd.build_system)
+                .unwrap_or(0),
+        )
+        .interact()?;
+    cmd.build_system = build_systems[idx];
+
+    let license: String = Input::new()
+        .with_prompt("License (SPDX identifier, empty for none)")
+        .default(cmd.license.take().unwrap_or_default())
+        .allow_empty(true)
+        .validate_with(|input: &String| -> Result<(), String> {
+            if input.is_empty() || input.parse::<&dyn License>().is_ok() {
+                Ok(())
+            } else {
+                Err(format!(
+                    "'{}' is not a known SPDX license identifier",
+                    input
+                ))
+            }
+        })
+        .interact_text()?;
+    cmd.license = Some(license).filter(|x| !x.is_empty());
+
+    let (default_author, default_email) = match get_default_author() {
+        Some((name, email)) => (Some(name), Some(email)),
+        None => (None, None),
+    };
+    let author: String = Input::new()
+        .with_prompt("Author name")
+        .default(cmd.author.take().or(default_author).unwrap_or_default())
+        .allow_empty(true)
+        .interact_text()?;
+    let email: String = Input::new()
+        .with_prompt("Author email")
+        .default(cmd.email.take().or(default_email).unwrap_or_default())
+        .allow_empty(true)
+        .interact_text()?;
+    cmd.author = Some(author);
+    cmd.email = Some(email);
+
+    Ok(())
+}
+
+/// The template tools that are installed into the self venv on demand.
+const COOKIECUTTER_REQUIREMENT: &str = "cookiecutter==2.1.1";
+const COPIER_REQUIREMENT: &str = "copier==7.2.0";
+
+/// Creates the project from a Cookiecutter or Copier template.
+fn init_from_template(
+    template: &str,
+    dir: &Path,
+    name: &str,
+    extra: &[String],
+    output: CommandOutput,
+) -> Result<(), Error> {
+    for var in extra {
+        if !var.contains('=') {
+            bail!("invalid template variable '{}' (expected key=value)", var);
+        }
+    }
+
+    // remote templates are cloned first so that we can tell which tool they are for
+    let checkout = tempfile::tempdir()?;
+    let source = if Path::new(template).is_dir() {
+        PathBuf::from(template)
+    } else {
+        let status = Command::new("git")
+            .arg("clone")
+            .arg("--depth=1")
+            .arg("--quiet")
+            .arg(template)
+            .arg(checkout.path())
+            .status()
+            .context("unable to run git")?;
+        if !status.success() {
+            bail!("failed to clone template {}", template);
+        }
+        checkout.path().to_path_buf()
+    };
+    let is_copier = source.join("copier.yml").is_file() || source.join("copier.yaml").is_file();
+    if !is_copier && !source.join("cookiecutter.json").is_file() {
+        bail!(
+            "{} is neither a Cookiecutter nor a Copier template",
+            template
+        );
+    }
+
+    let self_venv = ensure_self_venv(output)?;
+    let (tool, requirement) = if is_copier {
+        ("copier", COPIER_REQUIREMENT)
+    } else {
+        ("cookiecutter", COOKIECUTTER_REQUIREMENT)
+    };
+    let tool_bin = self_venv.join(VENV_BIN).join(tool);
+    if !tool_bin.is_file() {
+        if output != CommandOutput::Quiet {
+            eprintln!("Installing {}", requirement);
+        }
+        let mut pip_cmd = Command::new(get_venv_python_bin(&self_venv));
+        pip_cmd
+            .arg("-mpip")
+            .arg("install")
+            .arg(requirement)
+            .env("PYTHONWARNINGS", "ignore");
+        if output != CommandOutput::Verbose {
+            pip_cmd.arg("--quiet");
+        }
+        let status = pip_cmd.status().context("unable to run pip")?;
+        if !status.success() {
+            bail!("failed to install {}", tool);
+        }
+    }
+
+    let project_name_var = format!("project_name={}", name);
+    if is_copier {
+        let mut copier_cmd = Command::new(&tool_bin);
+        copier_cmd.arg("copy").arg("--defaults");
+        for var in Some(&project_name_var).into_iter().chain(extra) {
+            copier_cmd.arg("--data").arg(var);
+        }
+        if output == CommandOutput::Quiet {
+            copier_cmd.arg("--quiet");
+        }
+        copier_cmd.arg(&source).arg(dir);
+        let status = copier_cmd.status().context("unable to run copier")?;
+        if !status.success() {
+            bail!("failed to render template");
+        }
+    } else {
+        // cookiecutter picks the name of the folder it creates from the
+        // template, so render into a scratch folder next to the target and
+        // move the result into place.
+        let parent = dir.parent().unwrap_or(dir);
+        fs::create_dir_all(parent).ok();
+        let scratch = tempfile::tempdir_in(parent)?;
+        let mut cookiecutter_cmd = Command::new(&tool_bin);
+        cookiecutter_cmd
+            .arg("--no-input")
+            .arg("--output-dir")
+            .arg(scratch.path())
+            .arg(&source)
+            .arg(&project_name_var)
+            .args(extra);
+        if output == CommandOutput::Verbose {
+            cookiecutter_cmd.arg("--verbose");
+        }
+        let status = cookiecutter_cmd
+            .status()
+            .context("unable to run cookiecutter")?;
+        if !status.success() {
+            bail!("failed to render template");
+        }
+        let rendered = match fs::read_dir(scratch.path())?.next() {
+            Some(entry) => entry?.path(),
+            None => bail!("template did not create any files"),
+        };
+        if !dir.exists() {
+            fs::rename(&rendered, dir).context("failed to move rendered template")?;
+        } else {
+            for entry in fs::read_dir(&rendered)? {
+                let entry = entry?;
+                let target = dir.join(entry.file_name());
+                if target.exists() {
+                    bail!("{} already exists", target.display());
+                }
+                fs::rename(entry.path(), target).context("failed to move rendered template")?;
+            }
+        }
+    }
+
+    if output != CommandOutput::Quiet {
+        eprintln!(
+            "{} Initialized project in {}",
+            style("success:").green(),
+            dir.display()
+        );
+    }
+
+    if !dir.join("pyproject.toml").is_file() {
+        if output != CommandOutput::Quiet {
+            eprintln!(
+                "{} template did not create a pyproject.toml, skipping sync",
+                style("warning:").yellow()
+            );
+        }
+        return Ok(());
+    }
+    env::set_current_dir(dir)?;
+    sync(SyncOptions {
+        output,
+        dev: true,
+        mode: SyncMode::Regular,
+        ..SyncOptions::default()
+    })
+    .context("failed to sync the new project")
+}
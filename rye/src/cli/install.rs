@@ -5,7 +5,7 @@ use clap::Parser;
 use pep508_rs::Requirement;
 
 use crate::cli::add::ReqExtras;
-use crate::installer::{install, resolve_local_requirement};
+use crate::installer::{inject, install, resolve_local_requirement};
 use crate::sources::PythonVersionRequest;
 use crate::utils::CommandOutput;
 
@@ -19,6 +19,15 @@ pub struct Args {
     /// Include scripts from a given dependency.
     #[arg(long)]
     include_dep: Vec<String>,
+    /// Install this package into the already installed tool instead (can be supplied multiple times).
+    #[arg(
+        long,
+        value_name = "PACKAGE",
+        conflicts_with = "include_dep",
+        conflicts_with = "python",
+        conflicts_with = "force"
+    )]
+    inject: Vec<String>,
     /// Optionally the Python version to use.
     #[arg(short, long)]
     python: Option<String>,
@@ -36,6 +45,18 @@ pub struct Args {
 pub fn execute(mut cmd: Args) -> Result<(), Error> {
     let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
 
+    if !cmd.inject.is_empty() {
+        let packages = cmd
+            .inject
+            .iter()
+            .map(|x| {
+                x.parse::<Requirement>()
+                    .with_context(|| format!("failed to parse requirement '{}'", x))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        return inject(&cmd.requirement, &packages, output);
+    }
+
     let mut requirement = match resolve_local_requirement(Path::new(&cmd.requirement), output)? {
         Some(req) => req,
         None => cmd.requirement.parse::<Requirement>().with_context(|| {
@@ -56,17 +77,17 @@ pub fn execute(mut cmd: Args) -> Result<(), Error> {
     cmd.req_extras.force_absolute();
     cmd.req_extras.apply_to_requirement(&mut requirement)?;
 
-    let py_ver: PythonVersionRequest = match cmd.python {
-        Some(ref py) => py.parse()?,
-        None => PythonVersionRequest {
-            kind: None,
-            major: 3,
-            minor: None,
-            patch: None,
-            suffix: None,
-        },
+    let py_ver: Option<PythonVersionRequest> = match cmd.python {
+        Some(ref py) => Some(py.parse()?),
+        None => None,
     };
 
-    install(requirement, &py_ver, cmd.force, &cmd.include_dep, output)?;
+    install(
+        requirement,
+        py_ver.as_ref(),
+        cmd.force,
+        &cmd.include_dep,
+        output,
+    )?;
     Ok(())
 }
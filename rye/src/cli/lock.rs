@@ -1,7 +1,11 @@
-use anyhow::Error;
+use std::path::PathBuf;
+use std::{env, fs};
+
+use anyhow::{anyhow, Context, Error};
 use clap::Parser;
 
-use crate::lock::LockOptions;
+use crate::lock::{LockOptions, LockStrategy, ResolutionMode};
+use crate::pyproject::PyProject;
 use crate::sync::{sync, SyncMode, SyncOptions};
 use crate::utils::CommandOutput;
 
@@ -18,30 +22,110 @@ pub struct Args {
     #[arg(long)]
     update: Vec<String>,
     /// Update all packages to the latest
-    #[arg(long)]
+    #[arg(long, visible_alias = "upgrade-all")]
     update_all: bool,
     /// Update to pre-release versions
     #[arg(long)]
     pre: bool,
     /// Extras/features to enable when locking the workspace.
-    #[arg(long)]
+    #[arg(long, visible_alias = "extra")]
     features: Vec<String>,
     /// Enables all features.
-    #[arg(long)]
+    #[arg(long, visible_alias = "all-extras")]
     all_features: bool,
+    /// Print which packages changed in the lockfiles.
+    #[arg(long)]
+    diff: bool,
+    /// The package index to lock against (defaults to PyPI).
+    #[arg(long)]
+    index_url: Option<String>,
+    /// An additional package index to lock against (can be supplied multiple times).
+    #[arg(long = "extra-index-url")]
+    extra_index_urls: Vec<String>,
+    /// The resolver algorithm to use.
+    ///
+    /// `backtracking` always produces consistent pins.  `legacy` is faster on
+    /// large dependency trees, but can pin packages that conflict.
+    #[arg(long, default_value = "backtracking")]
+    strategy: LockStrategy,
+    /// Which versions to pick.
+    ///
+    /// `lowest-direct` pins the direct dependencies to the lower bound of
+    /// their version specifier to test against the minimum versions.
+    /// Defaults to the mode the lockfile was generated with (`highest` for
+    /// new lockfiles).
+    #[arg(long)]
+    resolution_mode: Option<ResolutionMode>,
+    /// Leave this package out of the lockfiles (can be supplied multiple times).
+    #[arg(long, value_name = "PACKAGE")]
+    exclude: Vec<String>,
+    /// Add a `# via` comment to every package with the chains of packages that require it.
+    #[arg(long)]
+    annotate: bool,
+    /// Stop adding `# via` comments to the lockfiles.
+    #[arg(long, conflicts_with = "annotate")]
+    no_annotate: bool,
+    /// Record the sdist URL and hash of every package in the lockfiles.
+    #[arg(long)]
+    with_sources: bool,
+    /// Stop recording the sdists in the lockfiles.
+    #[arg(long, conflicts_with = "with_sources")]
+    without_sources: bool,
+    /// Write the lockfile here and remember it in `tool.rye.lock-file`.
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// Only print the changes to the lockfiles and exit with 1 if there are any.
+    #[arg(long, conflicts_with = "output")]
+    dry_run: bool,
 }
 
 pub fn execute(cmd: Args) -> Result<(), Error> {
     let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
+    if let Some(ref lockfile) = cmd.output {
+        let pyproject = PyProject::discover()?;
+        // the lockfile is shared by the whole workspace
+        let mut pyproject = match pyproject.workspace() {
+            Some(workspace) => PyProject::load(&workspace.path().join("pyproject.toml"))?,
+            None => pyproject,
+        };
+        let lockfile = env::current_dir()?.join(lockfile);
+        let rel_path = pathdiff::diff_paths(&lockfile, pyproject.workspace_path())
+            .ok_or_else(|| anyhow!("unable to make {} relative", lockfile.display()))?;
+        pyproject.set_lock_file(&rel_path.to_string_lossy().replace('\\', "/"))?;
+        pyproject.save()?;
+        if let Some(parent) = lockfile.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create {}", parent.display()))?;
+        }
+    }
     sync(SyncOptions {
         output,
         mode: SyncMode::LockOnly,
+        show_diff: cmd.diff,
+        dry_run: cmd.dry_run,
+        check: cmd.dry_run,
         lock_options: LockOptions {
             update: cmd.update,
             update_all: cmd.update_all,
             pre: cmd.pre,
             features: cmd.features,
             all_features: cmd.all_features,
+            with_sources: match (cmd.with_sources, cmd.without_sources) {
+                (true, _) => Some(true),
+                (_, true) => Some(false),
+                _ => None,
+            },
+            index_url: cmd.index_url,
+            extra_index_urls: cmd.extra_index_urls,
+            strategy: cmd.strategy,
+            resolution_mode: cmd.resolution_mode,
+            exclude: cmd.exclude,
+            annotate: match (cmd.annotate, cmd.no_annotate) {
+                (true, _) => Some(true),
+                (_, true) => Some(false),
+                _ => None,
+            },
+            ..LockOptions::default()
         },
         ..SyncOptions::default()
     })?;
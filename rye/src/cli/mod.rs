@@ -3,12 +3,17 @@ use clap::Parser;
 
 mod add;
 mod build;
+mod credentials;
+mod env;
+mod export;
 mod fetch;
+mod generate_setup_py;
 mod init;
 mod install;
 mod lock;
 mod make_req;
 mod pin;
+mod prune;
 mod publish;
 mod remove;
 mod run;
@@ -20,6 +25,7 @@ mod sync;
 mod toolchain;
 mod tools;
 mod uninstall;
+mod workspace;
 
 use git_testament::git_testament;
 
@@ -41,12 +47,17 @@ struct Args {
 enum Command {
     Add(add::Args),
     Build(build::Args),
+    Credentials(credentials::Args),
+    Env(env::Args),
+    Export(export::Args),
     Fetch(fetch::Args),
+    GenerateSetupPy(generate_setup_py::Args),
     Init(init::Args),
     Install(install::Args),
     Lock(lock::Args),
     MakeReq(make_req::Args),
     Pin(pin::Args),
+    Prune(prune::Args),
     Publish(publish::Args),
     Remove(remove::Args),
     Run(run::Args),
@@ -58,6 +69,7 @@ enum Command {
     #[command(name = "self")]
     Rye(rye::Args),
     Uninstall(uninstall::Args),
+    Workspace(workspace::Args),
 }
 
 pub fn execute() -> Result<(), Error> {
@@ -76,12 +88,17 @@ pub fn execute() -> Result<(), Error> {
     match cmd {
         Command::Add(cmd) => add::execute(cmd),
         Command::Build(cmd) => build::execute(cmd),
+        Command::Credentials(cmd) => credentials::execute(cmd),
+        Command::Env(cmd) => env::execute(cmd),
+        Command::Export(cmd) => export::execute(cmd),
         Command::Fetch(cmd) => fetch::execute(cmd),
+        Command::GenerateSetupPy(cmd) => generate_setup_py::execute(cmd),
         Command::Init(cmd) => init::execute(cmd),
         Command::Install(cmd) => install::execute(cmd),
         Command::Lock(cmd) => lock::execute(cmd),
         Command::MakeReq(cmd) => make_req::execute(cmd),
         Command::Pin(cmd) => pin::execute(cmd),
+        Command::Prune(cmd) => prune::execute(cmd),
         Command::Publish(cmd) => publish::execute(cmd),
         Command::Remove(cmd) => remove::execute(cmd),
         Command::Run(cmd) => run::execute(cmd),
@@ -92,6 +109,7 @@ pub fn execute() -> Result<(), Error> {
         Command::Tools(cmd) => tools::execute(cmd),
         Command::Rye(cmd) => rye::execute(cmd),
         Command::Uninstall(cmd) => uninstall::execute(cmd),
+        Command::Workspace(cmd) => workspace::execute(cmd),
     }
 }
 
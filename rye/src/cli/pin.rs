@@ -2,12 +2,16 @@ use std::env;
 use std::fs;
 
 use anyhow::Context;
-use anyhow::{anyhow, Error};
+use anyhow::{anyhow, bail, Error};
 use clap::Parser;
 
-use crate::platform::get_pinnable_version;
+use crate::bootstrap::fetch;
+use crate::platform::{
+    get_global_python_version_filepath, get_pinnable_version, get_toolchain_python_bin,
+};
 use crate::pyproject::PyProject;
-use crate::sources::PythonVersionRequest;
+use crate::sources::{PythonVersion, PythonVersionRequest};
+use crate::utils::CommandOutput;
 
 /// Pins a Python version to this project.
 ///
@@ -15,28 +19,75 @@ use crate::sources::PythonVersionRequest;
 /// Additionally it will update `requires-python` in the `pyproject.toml`
 /// if it's lower than the current version.  This can be disabled by passing
 /// `--no-update-requires-python`.
+///
+/// With `--global` the version is written to the global pin instead which
+/// is used by projects that do not have a `.python-version` file.
+///
+/// With `--verify` the pin is only written if the toolchain is installed,
+/// `--auto-fetch` downloads it first if needed.
 #[derive(Parser, Debug)]
 pub struct Args {
     /// The version of Python to pin.
-    version: String,
+    #[arg(required_unless_present = "show_global")]
+    version: Option<String>,
     /// Prevent updating requires-python in the pyproject.toml.
     #[arg(long)]
     no_update_requires_python: bool,
+    /// Set the global default Python version instead.
+    #[arg(long)]
+    global: bool,
+    /// Print the global default Python version.
+    #[arg(long, conflicts_with_all = ["version", "global"])]
+    show_global: bool,
+    /// Fail if the pinned version is not an installed toolchain.
+    #[arg(long, conflicts_with = "show_global")]
+    verify: bool,
+    /// Fetch the pinned version if it is not installed yet.
+    #[arg(long, conflicts_with_all = ["show_global", "verify"])]
+    auto_fetch: bool,
 }
 
 pub fn execute(cmd: Args) -> Result<(), Error> {
-    let req: PythonVersionRequest = cmd.version.parse()?;
+    let global_version_file = get_global_python_version_filepath();
+    if cmd.show_global {
+        match fs::read_to_string(&global_version_file) {
+            Ok(contents) => println!("{}", contents.trim()),
+            Err(_) => bail!("no global Python version pinned"),
+        }
+        return Ok(());
+    }
+
+    let req: PythonVersionRequest = cmd.version.unwrap_or_default().parse()?;
     let to_write = get_pinnable_version(&req)
         .ok_or_else(|| anyhow!("unsupported/unknown version for this platform"))?;
 
-    let version_file = match PyProject::discover() {
-        Ok(proj) => proj.root_path().join(".python-version"),
-        Err(_) => env::current_dir()?.join(".python-version"),
+    if cmd.verify || cmd.auto_fetch {
+        let pinned_req: PythonVersionRequest = to_write.parse()?;
+        if !is_installed(&pinned_req)? {
+            if cmd.auto_fetch {
+                fetch(&pinned_req, CommandOutput::Normal)?;
+            } else {
+                bail!(
+                    "toolchain {} is not installed, run `rye fetch {}` to install it",
+                    to_write,
+                    to_write
+                );
+            }
+        }
+    }
+
+    let version_file = if cmd.global {
+        global_version_file
+    } else {
+        match PyProject::discover() {
+            Ok(proj) => proj.root_path().join(".python-version"),
+            Err(_) => env::current_dir()?.join(".python-version"),
+        }
     };
     fs::write(&version_file, format!("{}\n", to_write))
-        .context("failed to write .python-version file")?;
+        .with_context(|| format!("failed to write {}", version_file.display()))?;
 
-    if !cmd.no_update_requires_python {
+    if !cmd.no_update_requires_python && !cmd.global {
         if let Ok(mut pyproject_toml) = PyProject::discover() {
             let new_version = to_write.parse::<PythonVersionRequest>()?;
             if let Some(curr_version) = pyproject_toml.target_python_version() {
@@ -52,3 +103,11 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
 
     Ok(())
 }
+
+/// Checks if the toolchain for a version request is installed.
+fn is_installed(req: &PythonVersionRequest) -> Result<bool, Error> {
+    match PythonVersion::try_from(req.clone()) {
+        Ok(ver) => Ok(get_toolchain_python_bin(&ver)?.is_file()),
+        Err(_) => Ok(false),
+    }
+}
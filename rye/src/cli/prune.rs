@@ -0,0 +1,186 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Error};
+use clap::Parser;
+use console::style;
+use indicatif::HumanBytes;
+
+use crate::config::Config;
+use crate::platform::{get_app_dir, get_canonical_py_path, get_python_version_from_pyenv_pin};
+use crate::pyproject::PyProject;
+use crate::sources::PythonVersion;
+use crate::utils::{CommandOutput, QuietExit};
+
+/// Removes unused toolchains and internal virtualenvs.
+///
+/// A downloaded toolchain is considered unused if neither rye itself, an
+/// installed tool, the current project nor the default or pinned version
+/// refers to it.  Toolchains are only removed with `--toolchains` as
+/// virtualenvs of other projects might still use them.  Registered
+/// toolchains are never removed.
+#[derive(Parser, Debug)]
+pub struct Args {
+    /// Also remove unused toolchains (this breaks virtualenvs of other projects using them).
+    #[arg(long)]
+    toolchains: bool,
+    /// Only report what would be removed.
+    #[arg(long)]
+    dry_run: bool,
+    /// Do not ask for confirmation.
+    #[arg(short, long)]
+    yes: bool,
+    /// Enables verbose diagnostics.
+    #[arg(short, long)]
+    verbose: bool,
+    /// Turns off all output.
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+}
+
+pub fn execute(cmd: Args) -> Result<(), Error> {
+    let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
+    let app_dir = get_app_dir();
+
+    // the interpreters that virtualenvs managed by rye are based on
+    let mut venv_homes = Vec::new();
+    venv_homes.extend(read_venv_home(&app_dir.join("self")));
+    for venv in list_dirs(&app_dir.join("tools"))? {
+        venv_homes.extend(read_venv_home(&venv));
+    }
+    let mut pinned = HashSet::new();
+    if let Ok(project) = PyProject::discover() {
+        venv_homes.extend(read_venv_home(&project.venv_path()));
+        pinned.extend(project.venv_python_version().ok());
+    }
+    pinned.extend(get_python_version_from_pyenv_pin());
+    pinned.extend(
+        Config::current()
+            .default_toolchain()
+            .ok()
+            .and_then(|x| PythonVersion::try_from(x).ok()),
+    );
+
+    let mut kept = Vec::new();
+    let mut to_remove = Vec::new();
+    let mut removes_toolchains = false;
+    for dir in list_dirs(&app_dir.join("py"))? {
+        let version = match dir
+            .file_name()
+            .and_then(|x| x.to_str())
+            .and_then(|x| x.parse::<PythonVersion>().ok())
+        {
+            Some(version) => version,
+            None => continue,
+        };
+        let toolchain_dir = get_canonical_py_path(&version)?;
+        let toolchain_dir = toolchain_dir.canonicalize().unwrap_or(toolchain_dir);
+        if !cmd.toolchains
+            || pinned.contains(&version)
+            || venv_homes.iter().any(|x| x.starts_with(&toolchain_dir))
+        {
+            kept.push(version);
+        } else {
+            to_remove.push((format!("toolchain {}", version), dir));
+            removes_toolchains = true;
+        }
+    }
+
+    // pip-tools virtualenvs are keyed by kind and minor version
+    for venv in list_dirs(&app_dir.join("pip-tools"))? {
+        let key = venv.file_name().unwrap_or_default().to_string_lossy();
+        if !kept
+            .iter()
+            .any(|x| format!("{}@{}.{}", x.kind, x.major, x.minor) == key)
+        {
+            to_remove.push((format!("pip-tools virtualenv {}", key), venv.clone()));
+        }
+    }
+
+    if to_remove.is_empty() {
+        if output != CommandOutput::Quiet {
+            eprintln!("Nothing to prune");
+        }
+        return Ok(());
+    }
+
+    let mut total = 0;
+    for (description, path) in &to_remove {
+        let size = dir_size(path);
+        total += size;
+        if output != CommandOutput::Quiet || cmd.dry_run {
+            eprintln!(
+                "{} {} ({})",
+                description,
+                style(path.display()).dim(),
+                HumanBytes(size)
+            );
+        }
+    }
+
+    if cmd.dry_run {
+        eprintln!("Would free {}", HumanBytes(total));
+        return Ok(());
+    }
+
+    let prompt = if removes_toolchains {
+        "Virtualenvs of other projects that use these toolchains will stop working. Remove these?"
+    } else {
+        "Remove these?"
+    };
+    if !cmd.yes && !dialoguer::Confirm::new().with_prompt(prompt).interact()? {
+        eprintln!("Pruning cancelled!");
+        return Err(QuietExit(1).into());
+    }
+
+    for (_, path) in &to_remove {
+        fs::remove_dir_all(path).with_context(|| format!("failed to remove {}", path.display()))?;
+    }
+    if output != CommandOutput::Quiet {
+        eprintln!("{} freed {}", style("success:").green(), HumanBytes(total));
+    }
+
+    Ok(())
+}
+
+/// Lists the sub directories (but not symlinks) of a folder.
+fn list_dirs(path: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut rv = Vec::new();
+    if let Ok(iter) = fs::read_dir(path) {
+        for entry in iter {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                rv.push(entry.path());
+            }
+        }
+    }
+    rv.sort();
+    Ok(rv)
+}
+
+/// Returns the folder of the interpreter a virtualenv was created from.
+fn read_venv_home(venv: &Path) -> Option<PathBuf> {
+    let cfg = fs::read_to_string(venv.join("pyvenv.cfg")).ok()?;
+    cfg.lines().find_map(|line| {
+        let (key, value) = line.split_once('=')?;
+        if key.trim() == "home" {
+            let home = PathBuf::from(value.trim());
+            Some(home.canonicalize().unwrap_or(home))
+        } else {
+            None
+        }
+    })
+}
+
+fn dir_size(path: &Path) -> u64 {
+    let mut rv = 0;
+    for entry in fs::read_dir(path).into_iter().flatten().flatten() {
+        match entry.file_type() {
+            Ok(ty) if ty.is_dir() => rv += dir_size(&entry.path()),
+            Ok(_) => rv += entry.metadata().map_or(0, |x| x.len()),
+            Err(_) => {}
+        }
+    }
+    rv
+}
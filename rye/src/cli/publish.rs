@@ -1,26 +1,72 @@
-use std::io::{Read, Write};
-use std::path::PathBuf;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
-
-use age::{
-    secrecy::{ExposeSecret, Secret},
-    Decryptor, Encryptor,
-};
-use anyhow::{bail, Context, Error};
-use clap::Parser;
-use toml_edit::{Item, Table};
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
+use std::{env, fs};
+
+use age::secrecy::{ExposeSecret, Secret};
+use anyhow::{anyhow, bail, Context, Error};
+use clap::{Parser, ValueEnum};
+use console::style;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use pep440_rs::Version;
+use regex::Regex;
+use toml_edit::{Document, Item, Table};
 use url::Url;
 
-use crate::bootstrap::ensure_self_venv;
-use crate::platform::{get_credentials, write_credentials};
-use crate::pyproject::PyProject;
+use crate::bootstrap::{download_url, ensure_self_venv};
+use crate::platform::{decrypt, encrypt, escape_string, get_credentials, write_credentials};
+use crate::pyproject::{normalize_package_name, PyProject};
 use crate::utils::{get_venv_python_bin, CommandOutput};
 
+/// The kinds of distribution files to upload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum DistributionType {
+    /// Upload wheels and sdists.
+    #[default]
+    All,
+    /// Only upload wheels.
+    WheelOnly,
+    /// Only upload sdists.
+    SdistOnly,
+}
+
+impl DistributionType {
+    /// Checks if a file name is a distribution of this type.
+    fn matches(self, filename: &str) -> bool {
+        let is_wheel = filename.ends_with(".whl");
+        match self {
+            DistributionType::All => true,
+            DistributionType::WheelOnly => is_wheel,
+            DistributionType::SdistOnly => {
+                filename.ends_with(".tar.gz") || filename.ends_with(".zip")
+            }
+        }
+    }
+}
+
 /// Publish packages to a package repository.
 #[derive(Parser, Debug)]
 pub struct Args {
     /// The distribution files to upload to the repository (defaults to <workspace-root>/dist/*).
     dist: Option<Vec<PathBuf>>,
+    /// Publish the packages listed in this manifest file one after another.
+    #[arg(long, value_name = "MANIFEST", conflicts_with = "dist")]
+    batch: Option<PathBuf>,
+    /// Continue with the next package of a `--batch` if a publish fails.
+    #[arg(long, requires = "batch")]
+    keep_going: bool,
+    /// Only upload files whose name matches this glob (can be supplied multiple times).
+    #[arg(long)]
+    upload_only: Vec<String>,
+    /// Do not upload files whose name matches this glob (can be supplied multiple times).
+    #[arg(long)]
+    exclude: Vec<String>,
+    /// The kinds of distribution files to upload.
+    #[arg(long, value_enum, default_value_t)]
+    distribution_type: DistributionType,
     /// The repository to publish to (defaults to 'pypi').
     #[arg(short, long, default_value = "pypi")]
     repository: String,
@@ -36,9 +82,24 @@ pub struct Args {
     /// GPG identity used to sign files.
     #[arg(short, long)]
     identity: Option<String>,
+    /// Sign files with the GPG key with this key ID (implies `--sign`).
+    #[arg(long, value_name = "KEYID", conflicts_with = "identity")]
+    sign_with_key: Option<String>,
     /// Path to alternate CA bundle.
     #[arg(long)]
     cert: Option<PathBuf>,
+    /// Passes an extra argument to twine (can be supplied multiple times).
+    #[arg(long = "twine-arg", allow_hyphen_values = true)]
+    twine_args: Vec<String>,
+    /// A changelog to take the release notes from (defaults to CHANGELOG.md or CHANGELOG.rst).
+    #[arg(long)]
+    changelog: Option<PathBuf>,
+    /// Wait up to this many seconds for the upload to show up on the index.
+    #[arg(long, value_name = "SECONDS")]
+    wait_for_index: Option<u64>,
+    /// A webhook URL to notify after a successful upload (overrides `tool.rye.publish.announce-url`).
+    #[arg(long)]
+    announce: Option<Url>,
     /// Enables verbose diagnostics.
     #[arg(short, long)]
     verbose: bool,
@@ -47,20 +108,46 @@ pub struct Args {
     quiet: bool,
 }
 
-pub fn execute(cmd: Args) -> Result<(), Error> {
+pub fn execute(mut cmd: Args) -> Result<(), Error> {
     let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
     let venv = ensure_self_venv(output)?;
     let project = PyProject::discover()?;
 
-    // Get the files to publish.
-    let files = match cmd.dist {
-        Some(paths) => paths,
-        None => vec![project.workspace_path().join("dist").join("*")],
+    if let Some(ref key_id) = cmd.sign_with_key {
+        let fingerprint = find_gpg_fingerprint(key_id)?;
+        if output == CommandOutput::Verbose {
+            eprintln!("signing with GPG key {}", fingerprint);
+        }
+        cmd.identity = Some(fingerprint);
+        cmd.sign = true;
+    }
+
+    // Get the files to publish.  For a batch this happens per package.
+    let batch = match cmd.batch {
+        Some(ref manifest) => Some(load_batch_manifest(&project, manifest)?),
+        None => None,
+    };
+    let files = if batch.is_none() {
+        let files = resolve_dist_files(
+            cmd.dist
+                .clone()
+                .unwrap_or_else(|| vec![project.workspace_path().join("dist").join("*")]),
+            &cmd.upload_only,
+            &cmd.exclude,
+            cmd.distribution_type,
+        )?;
+        if files.is_empty() {
+            bail!("no distribution files to upload");
+        }
+        files
+    } else {
+        Vec::new()
     };
 
     // a. Get token from arguments and offer encryption, then store in credentials file.
     // b. Get token from ~/.rye/credentials keyed by provided repository and provide decryption option.
-    // c. Otherwise prompt for token and provide encryption option, storing the result in credentials.
+    // c. Get token from the environment (never stored or logged).
+    // d. Otherwise prompt for token and provide encryption option, storing the result in credentials.
     let repository = &cmd.repository;
 
     // If -r is pypi but the url isn't pypi then bail
@@ -73,8 +160,8 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
         .entry(repository)
         .or_insert(Item::Table(Table::new()));
 
-    let token = if let Some(token) = cmd.token {
-        let secret = Secret::new(token);
+    let token = if let Some(ref token) = cmd.token {
+        let secret = Secret::new(token.clone());
         let maybe_encrypted = prompt_maybe_encrypt(&secret)?;
         let maybe_encoded = maybe_encode(&secret, &maybe_encrypted);
         credentials[repository]["token"] = Item::Value(maybe_encoded.expose_secret().into());
@@ -90,6 +177,8 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
         let secret = Secret::new(token);
 
         prompt_maybe_decrypt(&secret)?
+    } else if let Some(token) = get_token_from_env(repository) {
+        Secret::new(token)
     } else {
         eprintln!("No access token found, generate one at: https://pypi.org/manage/account/token/");
         let token = prompt_for_token()?;
@@ -105,7 +194,204 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
         secret
     };
 
-    let mut publish_cmd = Command::new(get_venv_python_bin(&venv));
+    match batch {
+        Some(packages) => publish_batch(&venv, &packages, &token, &cmd, output),
+        None => publish_project(&venv, &project, files, &token, &cmd, output),
+    }
+}
+
+/// A package listed in a `--batch` manifest.
+struct BatchPackage {
+    project: PyProject,
+    dist: PathBuf,
+    version: String,
+}
+
+/// Loads the packages of a `--batch` manifest in publish order.
+///
+/// The manifest lists the packages as `[[package]]` tables with the project
+/// `name`, and optionally the `dist` folder (relative to the manifest) and
+/// the `version` that is expected to be published:
+///
+/// ```toml
+/// [[package]]
+/// name = "mylib-core"
+///
+/// [[package]]
+/// name = "mylib"
+/// dist = "dist"
+/// version = "1.0.0"
+/// ```
+fn load_batch_manifest(project: &PyProject, path: &Path) -> Result<Vec<BatchPackage>, Error> {
+    let doc = fs::read_to_string(path)
+        .with_context(|| format!("failed to read batch manifest {}", path.display()))?
+        .parse::<Document>()
+        .with_context(|| format!("failed to parse batch manifest {}", path.display()))?;
+    let base = env::current_dir()?.join(path.parent().unwrap_or(Path::new("")));
+
+    let mut rv = Vec::new();
+    let tables = match doc.get("package") {
+        Some(item) => item
+            .as_array_of_tables()
+            .ok_or_else(|| anyhow!("'package' in batch manifest must be an array of tables"))?,
+        None => bail!("batch manifest {} lists no packages", path.display()),
+    };
+    for table in tables.iter() {
+        let name = table
+            .get("name")
+            .and_then(|x| x.as_str())
+            .ok_or_else(|| anyhow!("package in batch manifest is missing a name"))?;
+        let package = match project.workspace() {
+            Some(workspace) => workspace.get_project(name)?,
+            None if project.normalized_name()? == normalize_package_name(name) => {
+                Some(PyProject::load(&project.toml_path())?)
+            }
+            None => None,
+        }
+        .ok_or_else(|| anyhow!("unknown project '{}'", name))?;
+        let project_version = package
+            .version()
+            .ok_or_else(|| anyhow!("project '{}' has no version", name))?
+            .to_string();
+        let version = match table.get("version").and_then(|x| x.as_str()) {
+            Some(version) if version != project_version => bail!(
+                "batch manifest expects {} {} but the project is at {}",
+                name,
+                version,
+                project_version
+            ),
+            _ => project_version,
+        };
+        let dist = match table.get("dist").and_then(|x| x.as_str()) {
+            Some(dist) => base.join(dist),
+            None => package.workspace_path().join("dist"),
+        };
+        rv.push(BatchPackage {
+            project: package,
+            dist,
+            version,
+        });
+    }
+    Ok(rv)
+}
+
+/// Publishes the packages of a batch one after another and prints a summary.
+fn publish_batch(
+    venv: &Path,
+    packages: &[BatchPackage],
+    token: &Secret<String>,
+    cmd: &Args,
+    output: CommandOutput,
+) -> Result<(), Error> {
+    let mut results = Vec::new();
+    let mut aborted = false;
+    for package in packages {
+        let name = package.project.name().unwrap_or("unknown");
+        if aborted {
+            results.push((name, &package.version, Err(None)));
+            continue;
+        }
+        if output != CommandOutput::Quiet {
+            eprintln!(
+                "publishing {} {}",
+                style(name).cyan(),
+                style(&package.version).cyan()
+            );
+        }
+        let rv = resolve_dist_files(
+            vec![package.dist.join("*")],
+            &cmd.upload_only,
+            &cmd.exclude,
+            cmd.distribution_type,
+        )
+        .and_then(|mut files| {
+            files.retain(|file| {
+                is_dist_of(
+                    &file.file_name().unwrap_or_default().to_string_lossy(),
+                    name,
+                    &package.version,
+                )
+            });
+            if files.is_empty() {
+                bail!("no distribution files to upload");
+            }
+            publish_project(venv, &package.project, files, token, cmd, output)
+        });
+        if let Err(ref err) = rv {
+            if output != CommandOutput::Quiet {
+                eprintln!("{} {:#}", style("error:").red(), err);
+            }
+            aborted = !cmd.keep_going;
+        }
+        results.push((name, &package.version, rv.map_err(Some)));
+    }
+
+    let failed = results
+        .iter()
+        .filter(|x| matches!(x.2, Err(Some(_))))
+        .count();
+    if output != CommandOutput::Quiet {
+        eprintln!();
+        eprintln!("Batch summary:");
+        for (name, version, rv) in &results {
+            match rv {
+                Ok(()) => eprintln!("  {} {} {}", style("published").green(), name, version),
+                Err(Some(err)) => {
+                    eprintln!(
+                        "  {}    {} {}: {:#}",
+                        style("failed").red(),
+                        name,
+                        version,
+                        err
+                    )
+                }
+                Err(None) => eprintln!("  {}   {} {}", style("skipped").yellow(), name, version),
+            }
+        }
+    }
+    if failed > 0 {
+        bail!("{} of {} packages failed to publish", failed, results.len());
+    }
+    Ok(())
+}
+
+/// Checks if a distribution file name is of the given package version.
+///
+/// Versions are compared according to PEP 440, so `1.0` matches `1.0.0`.
+fn is_dist_of(filename: &str, name: &str, version: &str) -> bool {
+    let name_and_version = if let Some(stem) = filename.strip_suffix(".whl") {
+        // the name of wheels cannot contain dashes, but the tags follow the version
+        let mut parts = stem.splitn(3, '-');
+        parts.next().zip(parts.next())
+    } else {
+        filename
+            .strip_suffix(".tar.gz")
+            .or_else(|| filename.strip_suffix(".zip"))
+            .and_then(|stem| stem.rsplit_once('-'))
+    };
+    match name_and_version {
+        Some((dist_name, dist_version)) => {
+            normalize_package_name(dist_name) == normalize_package_name(name)
+                && match (Version::from_str(dist_version), Version::from_str(version)) {
+                    (Ok(dist_version), Ok(version)) => dist_version == version,
+                    _ => dist_version == version,
+                }
+        }
+        None => false,
+    }
+}
+
+/// Uploads the distribution files of a project.
+fn publish_project(
+    venv: &Path,
+    project: &PyProject,
+    files: Vec<PathBuf>,
+    token: &Secret<String>,
+    cmd: &Args,
+    output: CommandOutput,
+) -> Result<(), Error> {
+    let repository = &cmd.repository;
+    let mut publish_cmd = Command::new(get_venv_python_bin(venv));
     publish_cmd
         .arg("-mtwine")
         .arg("--no-color")
@@ -120,12 +406,16 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
     if cmd.sign {
         publish_cmd.arg("--sign");
     }
-    if let Some(identity) = cmd.identity {
+    if let Some(ref identity) = cmd.identity {
         publish_cmd.arg("--identity").arg(identity);
     }
-    if let Some(cert) = cmd.cert {
+    if let Some(ref cert) = cmd.cert {
         publish_cmd.arg("--cert").arg(cert);
     }
+    if let Some(notes) = find_release_notes(project, cmd.changelog.as_deref(), output)? {
+        publish_cmd.arg("--comment").arg(notes);
+    }
+    publish_cmd.args(&cmd.twine_args);
 
     if output == CommandOutput::Quiet {
         publish_cmd.stdout(Stdio::null());
@@ -137,9 +427,366 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
         bail!("failed to publish files");
     }
 
+    if let Some(timeout) = cmd.wait_for_index {
+        wait_for_index(
+            project,
+            &cmd.repository_url,
+            Duration::from_secs(timeout),
+            output,
+        );
+    }
+
+    let announce_url = match cmd.announce {
+        Some(ref url) => Some(url.clone()),
+        None => project
+            .publish_announce_url()
+            .map(|x| x.parse::<Url>())
+            .transpose()
+            .context("invalid tool.rye.publish.announce-url")?,
+    };
+    if let Some(announce_url) = announce_url {
+        let payload = serde_json::json!({
+            "package": project.name(),
+            "version": project.version(),
+            "repository": repository,
+            "url": get_package_url(project, repository, &cmd.repository_url),
+        });
+        // a failed announcement must not fail the already finished upload
+        match post_json(&announce_url, &payload) {
+            Ok(()) => {
+                if output != CommandOutput::Quiet {
+                    eprintln!("Announced release to {}", announce_url);
+                }
+            }
+            Err(err) => {
+                if output != CommandOutput::Quiet {
+                    eprintln!(
+                        "{} failed to announce release: {:#}",
+                        style("warning:").yellow(),
+                        err
+                    );
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Looks up the fingerprint of a GPG key by its key ID.
+fn find_gpg_fingerprint(key_id: &str) -> Result<String, Error> {
+    let rv = Command::new("gpg")
+        .arg("--with-colons")
+        .arg("--list-keys")
+        .arg(key_id)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .output()
+        .context("unable to run gpg, is it installed?")?;
+    // the fingerprint of the primary key is the first `fpr` record
+    let stdout = String::from_utf8_lossy(&rv.stdout);
+    match stdout
+        .lines()
+        .find(|line| line.starts_with("fpr:"))
+        .and_then(|line| line.split(':').nth(9))
+        .filter(|fpr| !fpr.is_empty() && rv.status.success())
+    {
+        Some(fpr) => Ok(fpr.to_string()),
+        None => bail!(
+            "no GPG key found for '{}' (check `gpg --list-keys` or import the key first)",
+            key_id
+        ),
+    }
+}
+
+/// Finds the release notes of the current version in the changelog.
+///
+/// Without an explicit changelog, `CHANGELOG.md` or `CHANGELOG.rst` in the
+/// project root are used if they exist.
+fn find_release_notes(
+    project: &PyProject,
+    changelog: Option<&Path>,
+    output: CommandOutput,
+) -> Result<Option<String>, Error> {
+    let path = match changelog {
+        Some(path) => path.to_path_buf(),
+        None => match ["CHANGELOG.md", "CHANGELOG.rst"]
+            .iter()
+            .map(|x| project.root_path().join(x))
+            .find(|x| x.is_file())
+        {
+            Some(path) => path,
+            None => return Ok(None),
+        },
+    };
+    let version = match project.version() {
+        Some(version) => version,
+        None => return Ok(None),
+    };
+    let contents = fs::read_to_string(&path)
+        .with_context(|| format!("failed to read changelog {}", path.display()))?;
+    let notes = extract_changelog_section(&contents, version);
+    if notes.is_none() && changelog.is_some() && output != CommandOutput::Quiet {
+        eprintln!(
+            "{} no section for {} found in {}",
+            style("warning:").yellow(),
+            version,
+            path.display()
+        );
+    }
+    Ok(notes)
+}
+
+/// Extracts the section of a version from a markdown or reStructuredText changelog.
+///
+/// Headers like `## 1.0.0`, `## [1.0.0] - 2023-05-01`, `Version 1.0.0` or
+/// `v1.0.0 (2023-05-01)` with an rst underline are recognized.
+fn extract_changelog_section(contents: &str, version: &str) -> Option<String> {
+    let header_re = Regex::new(&format!(
+        r"(?i)^(#+)?\s*\[?(?:version\s+|release\s+)?v?{}\]?(?:$|[\s:(-])",
+        regex::escape(version)
+    ))
+    .unwrap();
+    let lines = contents.lines().collect::<Vec<_>>();
+    let is_underline = |line: &str| {
+        let line = line.trim_end();
+        line.len() >= 3
+            && line
+                .chars()
+                .all(|c| c == line.chars().next().unwrap() && "=-~^*+#".contains(c))
+    };
+
+    let (idx, level) = lines.iter().enumerate().find_map(|(idx, line)| {
+        let m = header_re.captures(line)?;
+        match m.get(1) {
+            Some(hashes) => Some((idx + 1, Some(hashes.as_str().len()))),
+            None if lines.get(idx + 1).map_or(false, |x| is_underline(x)) => Some((idx + 2, None)),
+            None => None,
+        }
+    })?;
+
+    let mut section = Vec::new();
+    for (offset, line) in lines[idx..].iter().enumerate() {
+        let is_next_header = match level {
+            Some(level) => {
+                let hashes = line.len() - line.trim_start_matches('#').len();
+                hashes > 0 && hashes <= level
+            }
+            None => lines
+                .get(idx + offset + 1)
+                .map_or(false, |x| is_underline(x) && !line.trim().is_empty()),
+        };
+        if is_next_header {
+            break;
+        }
+        section.push(*line);
+    }
+    let section = section.join("\n").trim().to_string();
+    if section.is_empty() {
+        None
+    } else {
+        Some(section)
+    }
+}
+
+/// Expands the given paths and applies the `--upload-only`, `--exclude` and
+/// `--distribution-type` filters.
+///
+/// Globs in the file name part of a path are expanded first, then files are
+/// kept if they match any of the `upload_only` patterns (or there are none)
+/// and none of the `exclude` patterns.  Patterns match against file names.
+fn resolve_dist_files(
+    paths: Vec<PathBuf>,
+    upload_only: &[String],
+    exclude: &[String],
+    distribution_type: DistributionType,
+) -> Result<Vec<PathBuf>, Error> {
+    let upload_only = build_globset(upload_only)?;
+    let exclude = build_globset(exclude)?;
+
+    let mut rv = Vec::new();
+    for path in paths {
+        rv.extend(expand_glob(&path)?);
+    }
+    rv.retain(|path| {
+        let filename = path.file_name().unwrap_or_default();
+        distribution_type.matches(&filename.to_string_lossy())
+            && upload_only
+                .as_ref()
+                .map_or(true, |set| set.is_match(filename))
+            && !exclude.as_ref().map_or(false, |set| set.is_match(filename))
+    });
+    Ok(rv)
+}
+
+fn build_globset(patterns: &[String]) -> Result<Option<GlobSet>, Error> {
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(Glob::new(pattern).with_context(|| format!("invalid glob '{}'", pattern))?);
+    }
+    Ok(Some(builder.build()?))
+}
+
+/// Expands a glob in the file name of a path to the matching files.
+fn expand_glob(path: &Path) -> Result<Vec<PathBuf>, Error> {
+    let filename = path.file_name().unwrap_or_default().to_string_lossy();
+    if !filename.contains(['*', '?', '[']) {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let matcher = Glob::new(&filename)
+        .with_context(|| format!("invalid glob '{}'", path.display()))?
+        .compile_matcher();
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let mut rv = Vec::new();
+    if let Ok(iter) = fs::read_dir(dir) {
+        for entry in iter {
+            let entry = entry?;
+            if entry.file_type()?.is_file() && matcher.is_match(entry.file_name()) {
+                rv.push(entry.path());
+            }
+        }
+    }
+    rv.sort();
+    Ok(rv)
+}
+
+/// Polls the JSON API of PyPI until the published version is available.
+///
+/// Running into the timeout only warns as the upload itself succeeded.
+fn wait_for_index(
+    project: &PyProject,
+    repository_url: &Url,
+    timeout: Duration,
+    output: CommandOutput,
+) {
+    let host = match repository_url.domain() {
+        Some("upload.pypi.org") => "pypi.org",
+        Some("test.pypi.org") => "test.pypi.org",
+        _ => {
+            if output != CommandOutput::Quiet {
+                eprintln!(
+                    "{} can only wait for PyPI and TestPyPI uploads",
+                    style("warning:").yellow()
+                );
+            }
+            return;
+        }
+    };
+    let (name, version) = match (project.name(), project.version()) {
+        (Some(name), Some(version)) => (name, version),
+        _ => {
+            if output != CommandOutput::Quiet {
+                eprintln!(
+                    "{} cannot wait for the index without a project name and version",
+                    style("warning:").yellow()
+                );
+            }
+            return;
+        }
+    };
+
+    let url = format!("https://{}/pypi/{}/{}/json", host, name, version);
+    if output != CommandOutput::Quiet {
+        eprint!("Waiting for {} {} to become available", name, version);
+    }
+    let started = Instant::now();
+    loop {
+        if download_url(&url, CommandOutput::Quiet).is_ok() {
+            if output != CommandOutput::Quiet {
+                eprintln!();
+                eprintln!(
+                    "Available at {}",
+                    style(format!("https://{}/project/{}/{}/", host, name, version)).cyan()
+                );
+            }
+            return;
+        }
+        if started.elapsed() >= timeout {
+            break;
+        }
+        if output != CommandOutput::Quiet {
+            eprint!(".");
+        }
+        thread::sleep(Duration::from_secs(1));
+    }
+    if output != CommandOutput::Quiet {
+        eprintln!();
+        eprintln!(
+            "{} {} {} did not show up within {}s",
+            style("warning:").yellow(),
+            name,
+            version,
+            timeout.as_secs()
+        );
+    }
+}
+
+/// Returns the URL under which the published version can be found.
+fn get_package_url(project: &PyProject, repository: &str, repository_url: &Url) -> String {
+    match (repository, project.name(), project.version()) {
+        ("pypi", Some(name), Some(version)) => {
+            format!("https://pypi.org/project/{}/{}/", name, version)
+        }
+        _ => repository_url.to_string(),
+    }
+}
+
+/// Sends a JSON payload to the given URL with a POST request.
+fn post_json(url: &Url, payload: &serde_json::Value) -> Result<(), Error> {
+    let mut handle = curl::easy::Easy::new();
+    handle.url(url.as_str())?;
+    handle.post(true)?;
+    handle.post_fields_copy(serde_json::to_string(payload)?.as_bytes())?;
+    let mut headers = curl::easy::List::new();
+    headers.append("Content-Type: application/json")?;
+    handle.http_headers(headers)?;
+    {
+        // discard the response body instead of printing it
+        let mut transfer = handle.transfer();
+        transfer.write_function(|data| Ok(data.len()))?;
+        transfer
+            .perform()
+            .with_context(|| format!("request to {} failed", url))?;
+    }
+    let code = handle.response_code()?;
+    if code >= 400 {
+        bail!("{} responded with status {}", url, code);
+    }
     Ok(())
 }
 
+/// Looks up the access token for a repository in the environment.
+///
+/// For PyPI this is `RYE_PYPI_TOKEN` or `PYPI_TOKEN`, for other repositories
+/// `RYE_TOKEN_<REPOSITORY>` with the name uppercased.
+fn get_token_from_env(repository: &str) -> Option<String> {
+    let names = if repository == "pypi" {
+        vec!["RYE_PYPI_TOKEN".to_string(), "PYPI_TOKEN".to_string()]
+    } else {
+        let suffix: String = repository
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() {
+                    c.to_ascii_uppercase()
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        vec![format!("RYE_TOKEN_{}", suffix)]
+    };
+    names
+        .iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|token| !token.is_empty())
+}
+
 fn prompt_for_token() -> Result<String, Error> {
     eprint!("Access token: ");
     let token = get_trimmed_user_input().context("failed to read provided token")?;
@@ -158,14 +805,7 @@ fn prompt_maybe_encrypt(secret: &Secret<String>) -> Result<Secret<Vec<u8>>, Erro
     let token = if phrase.expose_secret().is_empty() {
         secret.expose_secret().as_bytes().to_vec()
     } else {
-        // Do the encryption
-        let encryptor = Encryptor::with_user_passphrase(phrase);
-        let mut encrypted = vec![];
-        let mut writer = encryptor.wrap_output(&mut encrypted)?;
-        writer.write_all(secret.expose_secret().as_bytes())?;
-        writer.finish()?;
-
-        encrypted
+        encrypt(secret, phrase)?
     };
 
     Ok(Secret::new(token.to_vec()))
@@ -183,21 +823,7 @@ fn prompt_maybe_decrypt(secret: &Secret<String>) -> Result<Secret<String>, Error
         return Ok(secret.clone());
     }
 
-    // If a passphrase is provided we assume the secret is encoded bytes from encryption.
-    let bytes = hex::decode(pad_hex(secret.expose_secret().clone()))?;
-    if let Decryptor::Passphrase(decryptor) = Decryptor::new(bytes.as_slice())? {
-        // Do the decryption
-        let mut decrypted = vec![];
-        let mut reader = decryptor.decrypt(&phrase, None)?;
-        reader.read_to_end(&mut decrypted)?;
-
-        let token = String::from_utf8(decrypted).context("failed to parse utf-8")?;
-        let secret = Secret::new(token);
-
-        return Ok(secret);
-    }
-
-    bail!("failed to decrypt")
+    decrypt(secret, &phrase)
 }
 
 fn get_trimmed_user_input() -> Result<String, Error> {
@@ -222,14 +848,117 @@ fn maybe_encode(original_secret: &Secret<String>, new_secret: &Secret<Vec<u8>>)
     original_secret.clone()
 }
 
-fn pad_hex(s: String) -> String {
-    if s.len() % 2 == 1 {
-        format!("0{}", s)
-    } else {
-        s
+#[cfg(test)]
+mod test_is_dist_of {
+    use super::is_dist_of;
+
+    #[test]
+    fn test_is_dist_of() {
+        assert!(is_dist_of(
+            "my_pkg-1.0.0-py3-none-any.whl",
+            "my-pkg",
+            "1.0.0"
+        ));
+        assert!(is_dist_of("my-pkg-1.0.0.tar.gz", "My_Pkg", "1.0.0"));
+        assert!(is_dist_of("my_pkg-1.0-py3-none-any.whl", "my-pkg", "1.0.0"));
+        assert!(is_dist_of("my_pkg-1.0.0rc1.tar.gz", "my-pkg", "1.0.0-rc.1"));
+        assert!(!is_dist_of(
+            "my_pkg-1.0.1-py3-none-any.whl",
+            "my-pkg",
+            "1.0.0"
+        ));
+        assert!(!is_dist_of("other-1.0.0.tar.gz", "my-pkg", "1.0.0"));
+        assert!(!is_dist_of("my_pkg-1.0.0.egg", "my-pkg", "1.0.0"));
     }
 }
 
-fn escape_string(s: String) -> String {
-    s.trim().replace(['\\', '"'], "")
+#[cfg(test)]
+mod test_extract_changelog_section {
+    use super::extract_changelog_section;
+
+    #[test]
+    fn test_markdown_headings() {
+        let changelog = "\
+# Changelog
+
+## 1.1.0
+
+- Added a thing.
+
+### Fixes
+
+- Fixed a thing.
+
+## 1.0.0
+
+- Initial release.
+";
+        assert_eq!(
+            extract_changelog_section(changelog, "1.1.0").as_deref(),
+            Some("- Added a thing.\n\n### Fixes\n\n- Fixed a thing.")
+        );
+        assert_eq!(
+            extract_changelog_section(changelog, "1.0.0").as_deref(),
+            Some("- Initial release.")
+        );
+        assert_eq!(extract_changelog_section(changelog, "0.9.0"), None);
+    }
+
+    #[test]
+    fn test_heading_levels() {
+        let changelog = "# 2.0.0\n\nMajor.\n\n## Details\n\nMore.\n\n# 1.0.0\n\nFirst.\n";
+        assert_eq!(
+            extract_changelog_section(changelog, "2.0.0").as_deref(),
+            Some("Major.\n\n## Details\n\nMore.")
+        );
+        let changelog = "### 1.0.0\n\nFirst.\n\n## Older\n\nAncient.\n";
+        assert_eq!(
+            extract_changelog_section(changelog, "1.0.0").as_deref(),
+            Some("First.")
+        );
+    }
+
+    #[test]
+    fn test_version_prefixes() {
+        let changelog = "## [v1.2.0] - 2023-05-01\n\nBracketed.\n\n## v1.1.0\n\nPrefixed.\n";
+        assert_eq!(
+            extract_changelog_section(changelog, "1.2.0").as_deref(),
+            Some("Bracketed.")
+        );
+        assert_eq!(
+            extract_changelog_section(changelog, "1.1.0").as_deref(),
+            Some("Prefixed.")
+        );
+        // a version must not match the prefix of a longer one
+        assert_eq!(extract_changelog_section(changelog, "1.1"), None);
+    }
+
+    #[test]
+    fn test_underlined_headings() {
+        let changelog = "\
+Version 1.1.0
+-------------
+
+Underlined.
+
+v1.0.0 (2023-05-01)
+~~~~~~~~~~~~~~~~~~~
+
+Last section.
+";
+        assert_eq!(
+            extract_changelog_section(changelog, "1.1.0").as_deref(),
+            Some("Underlined.")
+        );
+        assert_eq!(
+            extract_changelog_section(changelog, "1.0.0").as_deref(),
+            Some("Last section.")
+        );
+    }
+
+    #[test]
+    fn test_empty_section() {
+        let changelog = "## 1.1.0\n\n## 1.0.0\n\nFirst.\n";
+        assert_eq!(extract_changelog_section(changelog, "1.1.0"), None);
+    }
 }
@@ -1,10 +1,11 @@
 use std::str::FromStr;
 
-use anyhow::Error;
+use anyhow::{Context, Error};
 use clap::Parser;
 use pep508_rs::Requirement;
 
 use crate::pyproject::{DependencyKind, PyProject};
+use crate::sync::{sync, SyncMode, SyncOptions};
 use crate::utils::{format_requirement, CommandOutput};
 
 /// Removes a package from this project.
@@ -18,6 +19,12 @@ pub struct Args {
     /// Remove this from an optional dependency group.
     #[arg(long, conflicts_with = "dev")]
     optional: Option<String>,
+    /// Only update the lockfiles but do not uninstall from the virtualenv.
+    #[arg(long)]
+    no_sync: bool,
+    /// Do not install the dev dependencies when syncing.
+    #[arg(long)]
+    no_dev: bool,
     /// Enables verbose diagnostics.
     #[arg(short, long)]
     verbose: bool,
@@ -55,5 +62,17 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
         }
     }
 
+    sync(SyncOptions {
+        output,
+        dev: !cmd.no_dev,
+        mode: if cmd.no_sync {
+            SyncMode::LockOnly
+        } else {
+            SyncMode::Regular
+        },
+        ..SyncOptions::default()
+    })
+    .context("failed to sync after removing dependencies")?;
+
     Ok(())
 }
@@ -1,14 +1,26 @@
 use std::env::{self, join_paths, split_paths};
 use std::ffi::OsString;
-use std::process::{Command, ExitStatus};
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 
-use anyhow::{bail, Context, Error};
+use anyhow::{anyhow, bail, Context, Error};
 use clap::Parser;
 use console::style;
+use globset::{Glob, GlobSet, GlobSetBuilder};
 
+use crate::bootstrap::ensure_self_venv;
+use crate::platform::get_app_dir;
 use crate::pyproject::{PyProject, Script};
 use crate::sync::{sync, SyncOptions};
-use crate::utils::{exec_spawn, success_status};
+use crate::utils::{
+    clear_pycache, exec_spawn, find_site_packages, get_venv_python_bin, success_status,
+    CommandOutput, QuietExit,
+};
 
 /// Runs a command installed into this package.
 #[derive(Parser, Debug)]
@@ -17,6 +29,88 @@ pub struct Args {
     /// List all commands
     #[arg(short, long)]
     list: bool,
+    /// Run the command as Python module (like `python -m`).
+    #[arg(short, long)]
+    module: bool,
+    /// Extra flags for the Python interpreter (e.g. `--python-args="-W error -u"`).
+    #[arg(long, allow_hyphen_values = true)]
+    python_args: Option<String>,
+    /// Run the script under cProfile.
+    #[arg(long)]
+    profile: bool,
+    /// Where to write the profile to (defaults to `<script-name>.prof`).
+    #[arg(long, requires = "profile")]
+    profile_output: Option<PathBuf>,
+    /// Run the script under the debugger and stop before it starts (uses pdbpp if possible).
+    #[arg(long, conflicts_with_all = ["profile", "parallel"])]
+    set_breakpoint: bool,
+    /// Load environment variables from a `.env` file (can be passed multiple times).
+    #[arg(long)]
+    env_file: Vec<PathBuf>,
+    /// Let variables from `--env-file` override the ones already set.
+    #[arg(long, requires = "env_file")]
+    override_env: bool,
+    /// Copy the output of the script into this log file.
+    #[arg(long)]
+    capture: Option<PathBuf>,
+    /// Like `--capture` but only write the log file if the script fails.
+    #[arg(long, conflicts_with = "capture")]
+    capture_on_fail: Option<PathBuf>,
+    /// Read the script's stdin from this file (`-` for the current stdin).
+    #[arg(long)]
+    stdin: Option<PathBuf>,
+    /// Connect the script's stdin to the null device so it cannot wait for input (e.g. on CI).
+    #[arg(long, conflicts_with_all = ["stdin", "tty"])]
+    stdin_tty_fix: bool,
+    /// Terminate the script if it runs longer than this many seconds.
+    #[arg(long, value_parser = parse_seconds)]
+    timeout: Option<Duration>,
+    /// Kill the script if it did not exit this many seconds after the timeout.
+    #[arg(long, requires = "timeout", value_parser = parse_seconds)]
+    kill_after: Option<Duration>,
+    /// Run this many instances of the script at once.
+    #[arg(
+        long,
+        value_name = "N",
+        conflicts_with = "profile",
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    parallel: Option<u32>,
+    /// Terminate all instances if they did not finish within this many seconds.
+    #[arg(
+        long,
+        requires = "parallel",
+        conflicts_with = "timeout",
+        value_parser = parse_seconds
+    )]
+    parallel_timeout: Option<Duration>,
+    /// Make sure no paths of rye's internal virtualenv leak into PATH or PYTHONPATH.
+    #[arg(long)]
+    no_self_venv: bool,
+    /// Start the script with only the variables needed to run Python.
+    #[arg(long)]
+    clean_env: bool,
+    /// Keep the environment variables matching this glob with `--clean-env` (can be passed multiple times).
+    #[arg(long, value_name = "PATTERN", requires = "clean_env")]
+    inherit_env: Vec<String>,
+    /// Print how long the script took after it finished.
+    #[arg(long)]
+    measure_time: bool,
+    /// Remove all `__pycache__` folders in the project before running the script.
+    #[arg(long)]
+    clear_pycache: bool,
+    /// Run the script in this working directory (relative to the current one).
+    #[arg(long)]
+    cwd: Option<PathBuf>,
+    /// Run the script in a new pseudo-terminal.
+    #[arg(long, conflicts_with = "no_tty")]
+    tty: bool,
+    /// Connect the script's stdin and stdout to pipes, even if rye runs in a terminal.
+    #[arg(long)]
+    no_tty: bool,
+    /// Enables verbose diagnostics.
+    #[arg(short, long)]
+    verbose: bool,
     /// The command to run
     #[command(subcommand)]
     cmd: Option<Cmd>,
@@ -28,6 +122,46 @@ enum Cmd {
     External(Vec<OsString>),
 }
 
+/// Extra options for invoking a script.
+#[derive(Default)]
+struct InvokeOptions<'a> {
+    /// Run the script under cProfile and write the profile here.
+    profile_output: Option<&'a Path>,
+    /// Extra environment variables for the script.
+    env: Vec<(String, String)>,
+    /// Copy the output of the script into this buffer.
+    capture: Option<&'a Mutex<Vec<u8>>>,
+    /// Read stdin from this file.
+    stdin: Option<&'a Path>,
+    /// Terminate the script after this time.
+    timeout: Option<Duration>,
+    /// Kill the script if it's still running this long after the timeout.
+    kill_after: Option<Duration>,
+    /// Prefix every line of output with this.
+    prefix: Option<String>,
+    /// Strip rye's internal virtualenv from the search paths.
+    no_self_venv: bool,
+    /// Extra arguments for the Python interpreter.
+    python_args: &'a [OsString],
+    /// Run in a new pseudo-terminal (`Some(true)`) or with pipes (`Some(false)`).
+    tty: Option<bool>,
+    /// The working directory of the script.
+    cwd: Option<&'a Path>,
+    /// Start from a clean environment, keeping only the matching variables.
+    clean_env: Option<&'a GlobSet>,
+    /// Run the script under this debugger module.
+    debugger: Option<&'a str>,
+}
+
+/// The variables that are kept with `--clean-env`.
+const CLEAN_ENV_VARS: &[&str] = &["HOME", "USER", "TMPDIR", "SYSTEMROOT", "TEMP", "TMP"];
+
+/// The file `--stdin-tty-fix` connects to the script's stdin.
+#[cfg(unix)]
+const NULL_DEVICE: &str = "/dev/null";
+#[cfg(windows)]
+const NULL_DEVICE: &str = "NUL";
+
 pub fn execute(cmd: Args) -> Result<(), Error> {
     let pyproject = PyProject::discover()?;
 
@@ -37,19 +171,622 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
     if cmd.list || cmd.cmd.is_none() {
         return list_scripts(&pyproject);
     }
-    let args = match cmd.cmd {
+    let mut args = match cmd.cmd {
         Some(Cmd::External(args)) => args,
         None => unreachable!(),
     };
+    if cmd.module {
+        args.splice(0..0, ["python".into(), "-m".into()]);
+    }
+    let python_args = match cmd.python_args {
+        Some(ref python_args) => shlex::split(python_args)
+            .ok_or_else(|| anyhow!("invalid quoting in --python-args"))?
+            .into_iter()
+            .map(OsString::from)
+            .collect(),
+        None => Vec::new(),
+    };
+
+    let mut env_vars = Vec::<(String, String)>::new();
+    for env_file in &cmd.env_file {
+        for (key, value) in load_env_file(env_file)? {
+            if !cmd.override_env && env::var_os(&key).is_some() {
+                continue;
+            }
+            env_vars.retain(|x| x.0 != key);
+            env_vars.push((key, value));
+        }
+    }
+
+    let cwd = match cmd.cwd {
+        Some(ref cwd) => {
+            let cwd = env::current_dir()?.join(cwd);
+            if !cwd.is_dir() {
+                bail!("working directory {} does not exist", cwd.display());
+            }
+            Some(cwd)
+        }
+        None => None,
+    };
+
+    let debugger = if cmd.set_breakpoint {
+        Some(find_debugger(&pyproject)?)
+    } else {
+        None
+    };
+    let clean_env = if cmd.clean_env {
+        let mut builder = GlobSetBuilder::new();
+        for pattern in &cmd.inherit_env {
+            builder.add(Glob::new(pattern).with_context(|| format!("invalid glob '{}'", pattern))?);
+        }
+        Some(builder.build()?)
+    } else {
+        None
+    };
+
+    let profile_output = if cmd.profile {
+        Some(cmd.profile_output.unwrap_or_else(|| {
+            let name = Path::new(&args[0])
+                .file_name()
+                .map(|x| x.to_os_string())
+                .unwrap_or_else(|| args[0].clone());
+            let mut rv = PathBuf::from(name);
+            rv.as_mut_os_string().push(".prof");
+            rv
+        }))
+    } else {
+        None
+    };
+    // with --cwd the profile is still written relative to the current directory
+    let profile_output = match profile_output {
+        Some(path) if cwd.is_some() => Some(env::current_dir()?.join(path)),
+        other => other,
+    };
+    let capture_output = cmd.capture.as_deref().or(cmd.capture_on_fail.as_deref());
+    let stdin = if cmd.stdin_tty_fix {
+        if cmd.verbose {
+            eprintln!(
+                "{} stdin of the script is redirected to {}",
+                style("warning:").yellow(),
+                NULL_DEVICE
+            );
+        }
+        Some(Path::new(NULL_DEVICE))
+    } else {
+        cmd.stdin.as_deref().filter(|x| *x != Path::new("-"))
+    };
+    let timeout = cmd.timeout.or(cmd.parallel_timeout);
+    let kill_after = cmd.kill_after;
+    let tty = if cmd.tty {
+        Some(true)
+    } else if cmd.no_tty {
+        Some(false)
+    } else {
+        None
+    };
+
+    if cmd.clear_pycache {
+        clear_pycache(&pyproject.root_path())?;
+    }
+
+    if profile_output.is_none()
+        && capture_output.is_none()
+        && timeout.is_none()
+        && cmd.parallel.is_none()
+        && tty.is_none()
+        && !cmd.measure_time
+    {
+        invoke_script(
+            &pyproject,
+            args,
+            true,
+            &InvokeOptions {
+                env: env_vars,
+                stdin,
+                no_self_venv: cmd.no_self_venv,
+                python_args: &python_args,
+                cwd: cwd.as_deref(),
+                clean_env: clean_env.as_ref(),
+                debugger,
+                ..Default::default()
+            },
+        )?;
+        unreachable!();
+    }
+
+    let capture = capture_output.map(|_| Mutex::new(format_capture_header(&args).into_bytes()));
+    let opts = InvokeOptions {
+        profile_output: profile_output.as_deref(),
+        env: env_vars,
+        capture: capture.as_ref(),
+        stdin,
+        timeout,
+        kill_after,
+        prefix: None,
+        no_self_venv: cmd.no_self_venv,
+        python_args: &python_args,
+        tty,
+        cwd: cwd.as_deref(),
+        clean_env: clean_env.as_ref(),
+        debugger,
+    };
+    let start = Instant::now();
+    let status = match cmd.parallel {
+        Some(count) => run_parallel(&pyproject, args, count, &opts)?,
+        None => invoke_script(&pyproject, args, false, &opts)?,
+    };
+    if cmd.measure_time {
+        eprintln!("Finished in {:.2}s", start.elapsed().as_secs_f64());
+    }
+
+    if let Some(profile_output) = profile_output {
+        if profile_output.is_file() {
+            eprintln!(
+                "Wrote profile to {}",
+                style(profile_output.display()).cyan()
+            );
+            if pyproject.venv_bin_path().join("snakeviz").is_file() {
+                eprintln!(
+                    "To visualize it run: rye run snakeviz {}",
+                    profile_output.display()
+                );
+            }
+        }
+    }
+
+    if let (Some(capture_output), Some(capture)) = (capture_output, capture) {
+        if cmd.capture.is_some() || !status.success() {
+            let mut log = capture.into_inner().unwrap();
+            writeln!(log, "\n# exit status: {}", status.code().unwrap_or(-1))?;
+            fs::write(capture_output, log).with_context(|| {
+                format!("failed to write log file {}", capture_output.display())
+            })?;
+            eprintln!("Wrote output to {}", style(capture_output.display()).cyan());
+        }
+    }
+
+    if !status.success() {
+        return Err(QuietExit(status.code().unwrap_or(1)).into());
+    }
+    Ok(())
+}
+
+/// Runs `count` instances of a script at once.
+///
+/// Each instance gets its index in `RYE_PARALLEL_INDEX` and its output is
+/// prefixed with it.  The status of the first failed instance is returned.
+fn run_parallel(
+    pyproject: &PyProject,
+    args: Vec<OsString>,
+    count: u32,
+    opts: &InvokeOptions,
+) -> Result<ExitStatus, Error> {
+    let statuses = thread::scope(|s| {
+        let handles = (0..count)
+            .map(|idx| {
+                let args = args.clone();
+                s.spawn(move || {
+                    let mut env = opts.env.clone();
+                    env.push(("RYE_PARALLEL_INDEX".into(), idx.to_string()));
+                    invoke_script(
+                        pyproject,
+                        args,
+                        false,
+                        &InvokeOptions {
+                            env,
+                            capture: opts.capture,
+                            stdin: opts.stdin,
+                            timeout: opts.timeout,
+                            kill_after: opts.kill_after,
+                            prefix: Some(format!("[{}] ", idx)),
+                            no_self_venv: opts.no_self_venv,
+                            python_args: opts.python_args,
+                            tty: opts.tty,
+                            cwd: opts.cwd,
+                            clean_env: opts.clean_env,
+                            ..Default::default()
+                        },
+                    )
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect::<Result<Vec<_>, _>>()
+    })?;
+    Ok(statuses
+        .into_iter()
+        .find(|status| !status.success())
+        .unwrap_or_else(success_status))
+}
+
+/// Runs the script `name` from `tool.rye.scripts` if the project defines it.
+///
+/// Returns `None` if there is no such script.
+pub fn invoke_hook(
+    pyproject: &PyProject,
+    name: &str,
+    env: Vec<(String, String)>,
+) -> Result<Option<ExitStatus>, Error> {
+    if !pyproject.list_scripts().contains(name) {
+        return Ok(None);
+    }
+    invoke_script(
+        pyproject,
+        vec![name.into()],
+        false,
+        &InvokeOptions {
+            env,
+            ..Default::default()
+        },
+    )
+    .map(Some)
+}
+
+/// Returns the header of the log file written by `--capture`.
+fn format_capture_header(args: &[OsString]) -> String {
+    let timestamp = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |x| x.as_secs());
+    let command = args
+        .iter()
+        .map(|x| shlex::quote(&x.to_string_lossy()).into_owned())
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!(
+        "# command: {}\n# timestamp: {}\n# rye: {}\n\n",
+        command,
+        timestamp,
+        env!("CARGO_PKG_VERSION")
+    )
+}
 
-    invoke_script(&pyproject, args, true)?;
-    unreachable!();
+/// Runs a command and waits for it, applying the capture and timeout options.
+fn spawn_and_wait(cmd: &mut Command, opts: &InvokeOptions) -> Result<ExitStatus, Error> {
+    if opts.tty == Some(true) {
+        return spawn_with_pty(cmd, opts);
+    }
+    let piped = opts.capture.is_some() || opts.prefix.is_some() || opts.tty == Some(false);
+    if piped {
+        cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+    }
+    let pipe_stdin = opts.tty == Some(false) && opts.stdin.is_none();
+    if pipe_stdin {
+        cmd.stdin(Stdio::piped());
+    }
+    let mut child = cmd.spawn().context("failed to spawn script")?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // this is not scoped as reading from our stdin can block forever
+        thread::spawn(move || io::copy(&mut io::stdin(), &mut stdin).ok());
+    }
+    let stdout = child.stdout.take();
+    let stderr = child.stderr.take();
+    thread::scope(|s| {
+        // both streams are drained at once so that neither of the pipes can
+        // fill up and block the script.
+        if let (Some(stdout), Some(stderr)) = (stdout, stderr) {
+            let prefix = opts.prefix.as_deref();
+            s.spawn(move || tee(stdout, io::stdout(), opts.capture, prefix).ok());
+            s.spawn(move || tee(stderr, io::stderr(), opts.capture, prefix).ok());
+        }
+        wait_with_timeout(&mut child, opts.timeout, opts.kill_after)
+    })
+}
+
+/// Spawns the script with its stdio connected to a new pseudo-terminal.
+///
+/// Our own terminal is switched to raw mode while the script runs so that
+/// every key press is forwarded to the script as is.
+#[cfg(unix)]
+fn spawn_with_pty(cmd: &mut Command, opts: &InvokeOptions) -> Result<ExitStatus, Error> {
+    use std::io::IsTerminal;
+    use std::os::fd::{AsRawFd, FromRawFd};
+    use std::os::unix::process::CommandExt;
+
+    use nix::libc;
+    use nix::pty::{openpty, Winsize};
+    use nix::sys::termios::{cfmakeraw, tcgetattr, tcsetattr, SetArg, Termios};
+    use nix::unistd::setsid;
+
+    struct RestoreTerminal(Option<Termios>);
+
+    impl Drop for RestoreTerminal {
+        fn drop(&mut self) {
+            if let Some(ref termios) = self.0 {
+                tcsetattr(io::stdin().as_raw_fd(), SetArg::TCSANOW, termios).ok();
+            }
+        }
+    }
+
+    // the pseudo-terminal gets the size of our own terminal if we have one
+    let mut winsize = Winsize {
+        ws_row: 0,
+        ws_col: 0,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let has_size =
+        unsafe { libc::ioctl(io::stdout().as_raw_fd(), libc::TIOCGWINSZ, &mut winsize) } == 0;
+    let pty = openpty(if has_size { Some(&winsize) } else { None }, None)
+        .context("failed to allocate a pseudo-terminal")?;
+    let master = unsafe { fs::File::from_raw_fd(pty.master) };
+    let slave = unsafe { fs::File::from_raw_fd(pty.slave) };
+
+    if opts.stdin.is_none() {
+        cmd.stdin(slave.try_clone()?);
+    }
+    cmd.stdout(slave.try_clone()?).stderr(slave.try_clone()?);
+    unsafe {
+        cmd.pre_exec(|| {
+            // a new session makes the pseudo-terminal the controlling terminal
+            setsid()?;
+            if libc::ioctl(1, libc::TIOCSCTTY, 0) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+
+    let stdin_fd = io::stdin().as_raw_fd();
+    let _restore = if opts.stdin.is_none() && io::stdin().is_terminal() {
+        let termios = tcgetattr(stdin_fd)?;
+        let mut raw = termios.clone();
+        cfmakeraw(&mut raw);
+        tcsetattr(stdin_fd, SetArg::TCSANOW, &raw)?;
+        RestoreTerminal(Some(termios))
+    } else {
+        RestoreTerminal(None)
+    };
+
+    let spawned = cmd.spawn();
+    // the command holds on to copies of the slave, they have to be closed so
+    // that reading from the master ends when the script exits.
+    cmd.stdin(Stdio::inherit())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit());
+    drop(slave);
+    let mut child = spawned.context("failed to spawn script")?;
+
+    if opts.stdin.is_none() {
+        let mut writer = master.try_clone()?;
+        thread::spawn(move || io::copy(&mut io::stdin(), &mut writer).ok());
+    }
+    thread::scope(|s| {
+        let prefix = opts.prefix.as_deref();
+        s.spawn(move || tee(master, io::stdout(), opts.capture, prefix).ok());
+        wait_with_timeout(&mut child, opts.timeout, opts.kill_after)
+    })
+}
+
+#[cfg(windows)]
+fn spawn_with_pty(_cmd: &mut Command, _opts: &InvokeOptions) -> Result<ExitStatus, Error> {
+    bail!("--tty is not supported on this platform");
+}
+
+/// Waits for a child, terminating it if it's not done after `timeout`.
+///
+/// If it's still running `kill_after` after it was asked to terminate, it's
+/// killed forcefully.
+fn wait_with_timeout(
+    child: &mut Child,
+    timeout: Option<Duration>,
+    kill_after: Option<Duration>,
+) -> Result<ExitStatus, Error> {
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return Ok(child.wait()?),
+    };
+    if let Some(status) = wait_for(child, timeout)? {
+        return Ok(status);
+    }
+
+    eprintln!(
+        "{} script timed out after {:?}, terminating",
+        style("error:").red(),
+        timeout
+    );
+    terminate(child)?;
+    if let Some(kill_after) = kill_after {
+        if let Some(status) = wait_for(child, kill_after)? {
+            return Ok(status);
+        }
+        eprintln!(
+            "{} script did not terminate, killing",
+            style("error:").red()
+        );
+        child.kill()?;
+    }
+    Ok(child.wait()?)
+}
+
+/// Waits up to `duration` for the child to exit.
+fn wait_for(child: &mut Child, duration: Duration) -> Result<Option<ExitStatus>, Error> {
+    let started = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if started.elapsed() >= duration {
+            return Ok(None);
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+/// Asks a child to terminate (`SIGTERM`).  On Windows it's just killed.
+fn terminate(child: &mut Child) -> Result<(), Error> {
+    #[cfg(unix)]
+    {
+        use nix::sys::signal::{kill, Signal};
+        use nix::unistd::Pid;
+        kill(Pid::from_raw(child.id() as i32), Signal::SIGTERM)?;
+    }
+    #[cfg(windows)]
+    {
+        child.kill()?;
+    }
+    Ok(())
+}
+
+/// Copies everything from `src` to `dst` and into the log.
+///
+/// With a prefix, the output is copied line by line with the prefix in front.
+fn tee(
+    src: impl Read,
+    mut dst: impl Write,
+    log: Option<&Mutex<Vec<u8>>>,
+    prefix: Option<&str>,
+) -> Result<(), io::Error> {
+    let mut src = BufReader::new(src);
+    let mut buf = Vec::new();
+    loop {
+        buf.clear();
+        match prefix {
+            Some(prefix) => {
+                if src.read_until(b'\n', &mut buf)? == 0 {
+                    return Ok(());
+                }
+                buf.splice(0..0, prefix.bytes());
+            }
+            None => {
+                let chunk = src.fill_buf()?;
+                if chunk.is_empty() {
+                    return Ok(());
+                }
+                buf.extend_from_slice(chunk);
+                let n = chunk.len();
+                src.consume(n);
+            }
+        }
+        if let Some(log) = log {
+            log.lock().unwrap().extend_from_slice(&buf);
+        }
+        // failing to echo (eg: because of a closed terminal) must not stop
+        // draining the pipe, so that error is ignored.
+        dst.write_all(&buf).and_then(|_| dst.flush()).ok();
+    }
+}
+
+/// Loads the variables from a `.env` file.
+fn load_env_file(path: &Path) -> Result<Vec<(String, String)>, Error> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("failed to read env file {}", path.display()))?;
+    let mut rv = Vec::new();
+    for (idx, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => bail!("invalid line {} in env file {}", idx + 1, path.display()),
+        };
+        let value = if value.len() >= 2
+            && (value.starts_with('\'') && value.ends_with('\'')
+                || value.starts_with('"') && value.ends_with('"'))
+        {
+            &value[1..value.len() - 1]
+        } else {
+            // unquoted values can have trailing comments
+            value.split(" #").next().unwrap_or("").trim_end()
+        };
+        rv.push((key.to_string(), value.to_string()));
+    }
+    Ok(rv)
+}
+
+/// Picks the debugger for `--set-breakpoint`.
+///
+/// pdbpp is installed into the virtualenv if it's missing.  If that fails
+/// (for instance because there is no network), the standard pdb is used.
+fn find_debugger(pyproject: &PyProject) -> Result<&'static str, Error> {
+    let venv = pyproject.venv_path();
+    let has_pdbpp = |venv: &Path| -> Result<bool, Error> {
+        Ok(find_site_packages(venv)?
+            .iter()
+            .any(|x| x.join("pdbpp.py").is_file()))
+    };
+    if has_pdbpp(&venv)? {
+        return Ok("pdbpp");
+    }
+
+    eprintln!("Installing pdbpp into the virtualenv");
+    let self_venv = ensure_self_venv(CommandOutput::Normal)?;
+    let status = Command::new(get_venv_python_bin(&self_venv))
+        .arg("-mpip")
+        .arg("--python")
+        .arg(get_venv_python_bin(&venv))
+        .arg("install")
+        .arg("-q")
+        .arg("pdbpp")
+        .env("PYTHONWARNINGS", "ignore")
+        .status()
+        .context("unable to run pip")?;
+    if status.success() && has_pdbpp(&venv)? {
+        Ok("pdbpp")
+    } else {
+        eprintln!(
+            "{} could not install pdbpp, falling back to pdb",
+            style("warning:").yellow()
+        );
+        Ok("pdb")
+    }
+}
+
+/// Parses a number of seconds passed on the command line.
+fn parse_seconds(value: &str) -> Result<Duration, String> {
+    let seconds = value
+        .parse::<f64>()
+        .map_err(|_| format!("'{}' is not a number of seconds", value))?;
+    Duration::try_from_secs_f64(seconds)
+        .map_err(|_| format!("'{}' is not a valid number of seconds", value))
+}
+
+/// Rewrites the arguments so that the script is run by the virtualenv's
+/// Python interpreter with some extra interpreter arguments.
+fn with_python_args(
+    pyproject: &PyProject,
+    args: Vec<OsString>,
+    python_args: &[OsString],
+) -> Result<Vec<OsString>, Error> {
+    let program = PathBuf::from(&args[0]);
+    let mut args = args.into_iter();
+    let mut rv = Vec::new();
+    if program
+        .file_name()
+        .map_or(false, |x| x.to_string_lossy().starts_with("python"))
+    {
+        rv.extend(args.next());
+    } else if is_python_script(&program) {
+        rv.push(get_venv_python_bin(&pyproject.venv_path()).into());
+    } else {
+        bail!("'{}' is not a Python script", program.display());
+    }
+    rv.extend(python_args.iter().cloned());
+    rv.extend(args);
+    Ok(rv)
+}
+
+/// Checks if a file is a Python script by its extension or shebang.
+fn is_python_script(path: &Path) -> bool {
+    if path.extension().map_or(false, |x| x == "py" || x == "pyw") {
+        return path.is_file();
+    }
+    let mut first_line = Vec::new();
+    fs::File::open(path)
+        .and_then(|f| BufReader::new(f.take(512)).read_until(b'\n', &mut first_line))
+        .map_or(false, |_| {
+            first_line.starts_with(b"#!") && String::from_utf8_lossy(&first_line).contains("python")
+        })
 }
 
 fn invoke_script(
     pyproject: &PyProject,
     mut args: Vec<OsString>,
     exec: bool,
+    opts: &InvokeOptions,
 ) -> Result<ExitStatus, Error> {
     let venv_bin = pyproject.venv_bin_path();
     let mut env_overrides = None;
@@ -82,9 +819,31 @@ fn invoke_script(
             if args.len() != 1 {
                 bail!("extra arguments to chained commands are not allowed");
             }
+            if opts.profile_output.is_some() {
+                bail!("chained commands cannot be profiled");
+            }
+            let chain_opts = InvokeOptions {
+                env: opts.env.clone(),
+                capture: opts.capture,
+                stdin: opts.stdin,
+                timeout: opts.timeout,
+                kill_after: opts.kill_after,
+                prefix: opts.prefix.clone(),
+                no_self_venv: opts.no_self_venv,
+                python_args: opts.python_args,
+                tty: opts.tty,
+                cwd: opts.cwd,
+                clean_env: opts.clean_env,
+                debugger: opts.debugger,
+                ..Default::default()
+            };
             for args in commands {
-                let status =
-                    invoke_script(pyproject, args.into_iter().map(Into::into).collect(), false)?;
+                let status = invoke_script(
+                    pyproject,
+                    args.into_iter().map(Into::into).collect(),
+                    false,
+                    &chain_opts,
+                )?;
                 if !status.success() {
                     if !exec {
                         return Ok(status);
@@ -103,27 +862,93 @@ fn invoke_script(
         }
     }
 
+    if let Some(profile_output) = opts.profile_output {
+        args = with_python_args(
+            pyproject,
+            args,
+            &["-mcProfile".into(), "-o".into(), profile_output.into()],
+        )?;
+    }
+    if let Some(debugger) = opts.debugger {
+        args = with_python_args(pyproject, args, &[format!("-m{}", debugger).into()])?;
+    }
+    // interpreter flags go in front of the cProfile ones
+    if !opts.python_args.is_empty() {
+        args = with_python_args(pyproject, args, opts.python_args)?;
+    }
+
     let mut cmd = Command::new(&args[0]);
     cmd.args(&args[1..]);
-    cmd.env("VIRTUAL_ENV", &*pyproject.venv_path());
-    if let Some(path) = env::var_os("PATH") {
-        let mut paths = split_paths(&path).collect::<Vec<_>>();
-        paths.insert(0, venv_bin.into());
-        let new_path = join_paths(paths)?;
-        cmd.env("PATH", new_path);
-    } else {
-        cmd.env("PATH", &*venv_bin);
+    if let Some(inherit) = opts.clean_env {
+        cmd.env_clear();
+        cmd.envs(env::vars_os().filter(|(key, _)| {
+            let key = key.to_string_lossy();
+            CLEAN_ENV_VARS.iter().any(|x| x.eq_ignore_ascii_case(&key)) || inherit.is_match(&*key)
+        }));
     }
+    let keeps_python_path = opts.clean_env.map_or(true, |x| x.is_match("PYTHONPATH"));
+    for (key, value) in venv_env_vars(pyproject, opts.no_self_venv)? {
+        if key == "PYTHONPATH" && !keeps_python_path {
+            continue;
+        }
+        match value {
+            Some(value) => cmd.env(key, value),
+            None => cmd.env_remove(key),
+        };
+    }
+    cmd.envs(opts.env.iter().map(|x| (&x.0, &x.1)));
     if let Some(env_overrides) = env_overrides {
         cmd.envs(env_overrides.iter());
     }
     cmd.env_remove("PYTHONHOME");
+    if let Some(cwd) = opts.cwd {
+        cmd.current_dir(cwd);
+    }
+    if let Some(stdin) = opts.stdin {
+        cmd.stdin(
+            fs::File::open(stdin).with_context(|| format!("failed to open {}", stdin.display()))?,
+        );
+    }
 
     if exec {
         match exec_spawn(&mut cmd)? {};
     } else {
-        Ok(cmd.status()?)
+        spawn_and_wait(&mut cmd, opts)
+    }
+}
+
+/// Returns the environment variables that activate the project's virtualenv.
+///
+/// With `no_self_venv` the paths of rye's internal virtualenv are removed
+/// from `PATH` and `PYTHONPATH`.  A value of `None` unsets the variable.
+pub fn venv_env_vars(
+    pyproject: &PyProject,
+    no_self_venv: bool,
+) -> Result<Vec<(&'static str, Option<OsString>)>, Error> {
+    let venv_bin = pyproject.venv_bin_path();
+    let self_venv = get_app_dir().join("self");
+    let is_self_venv_path = |path: &Path| no_self_venv && path.starts_with(&self_venv);
+
+    let mut rv = vec![(
+        "VIRTUAL_ENV",
+        Some(pyproject.venv_path().into_owned().into()),
+    )];
+    let mut paths = vec![venv_bin.into_owned()];
+    if let Some(path) = env::var_os("PATH") {
+        paths.extend(split_paths(&path).filter(|x| !is_self_venv_path(x)));
+    }
+    rv.push(("PATH", Some(join_paths(paths)?)));
+    if let Some(python_path) = env::var_os("PYTHONPATH") {
+        let paths = split_paths(&python_path)
+            .filter(|x| !is_self_venv_path(x))
+            .collect::<Vec<_>>();
+        if paths.is_empty() {
+            rv.push(("PYTHONPATH", None));
+        } else {
+            rv.push(("PYTHONPATH", Some(join_paths(paths)?)));
+        }
     }
+    Ok(rv)
 }
 
 fn list_scripts(pyproject: &PyProject) -> Result<(), Error> {
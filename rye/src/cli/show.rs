@@ -1,21 +1,50 @@
+use std::collections::HashMap;
+use std::fs;
 use std::path::Path;
 use std::process::Command;
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, SystemTime};
 
-use anyhow::{bail, Error};
+use anyhow::{anyhow, bail, Context, Error};
 use clap::Parser;
 use console::style;
+use pep440_rs::{Operator, Version};
+use pep508_rs::{Requirement, VersionOrUrl};
+use serde::Deserialize;
+use toml_edit::{Item, Table, Value};
 
-use crate::bootstrap::ensure_self_venv;
+use crate::bootstrap::{download_url, ensure_self_venv};
 use crate::consts::VENV_BIN;
-use crate::pyproject::{get_current_venv_python_version, PyProject};
+use crate::lock::read_locked_requirements;
+use crate::platform::get_app_dir;
+use crate::pyproject::{
+    get_current_venv_python_version, normalize_package_name, DependencyKind, PyProject,
+};
 use crate::utils::{get_venv_python_bin, CommandOutput};
 
+/// How long the latest versions fetched from PyPI are cached.
+const UPDATES_CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+
 /// Prints the current state of the project.
 #[derive(Parser, Debug)]
 pub struct Args {
-    /// Print the installed dependencies from the venv
+    /// Only print this field of the `[project]` table (e.g. `version` or `urls.homepage`).
+    field: Option<String>,
+    /// Print the `[project]` table (or the given field) as JSON.
     #[arg(long)]
+    json: bool,
+    /// Print the installed dependencies from the venv
+    #[arg(long, conflicts_with = "field", conflicts_with = "json")]
     installed_deps: bool,
+    /// Check PyPI for newer versions of the direct dependencies.
+    #[arg(
+        long,
+        conflicts_with = "field",
+        conflicts_with = "json",
+        conflicts_with = "installed_deps"
+    )]
+    check_updates: bool,
 }
 
 pub fn execute(cmd: Args) -> Result<(), Error> {
@@ -24,6 +53,12 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
     if cmd.installed_deps {
         return print_installed_deps(&project);
     }
+    if cmd.check_updates {
+        return print_updates(&project);
+    }
+    if cmd.field.is_some() || cmd.json {
+        return print_field(&project, cmd.field.as_deref(), cmd.json);
+    }
 
     println!(
         "project: {}",
@@ -69,6 +104,73 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
     Ok(())
 }
 
+fn print_field(project: &PyProject, field: Option<&str>, json: bool) -> Result<(), Error> {
+    let mut item = project
+        .project_metadata()
+        .ok_or_else(|| anyhow!("pyproject.toml has no [project] table"))?;
+    if let Some(field) = field {
+        for key in field.split('.') {
+            item = item
+                .get(key)
+                .ok_or_else(|| anyhow!("field '{}' is not set in [project]", field))?;
+        }
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&item_to_json(item))?);
+        return Ok(());
+    }
+
+    match item {
+        Item::Value(Value::String(s)) => println!("{}", s.value()),
+        Item::Value(Value::Array(arr)) => {
+            for value in arr.iter() {
+                match value.as_str() {
+                    Some(s) => println!("{}", s),
+                    None => println!("{}", value.to_string().trim()),
+                }
+            }
+        }
+        Item::Value(value) => println!("{}", value.to_string().trim()),
+        other => print!("{}", other),
+    }
+    Ok(())
+}
+
+/// Converts a toml item into the equivalent JSON value.
+fn item_to_json(item: &Item) -> serde_json::Value {
+    match item {
+        Item::None => serde_json::Value::Null,
+        Item::Value(value) => value_to_json(value),
+        Item::Table(table) => table_to_json(table),
+        Item::ArrayOfTables(tables) => tables.iter().map(table_to_json).collect(),
+    }
+}
+
+fn table_to_json(table: &Table) -> serde_json::Value {
+    table
+        .iter()
+        .map(|(key, value)| (key.to_string(), item_to_json(value)))
+        .collect::<serde_json::Map<_, _>>()
+        .into()
+}
+
+fn value_to_json(value: &Value) -> serde_json::Value {
+    match value {
+        Value::String(s) => s.value().clone().into(),
+        Value::Integer(i) => (*i.value()).into(),
+        Value::Float(f) => (*f.value()).into(),
+        Value::Boolean(b) => (*b.value()).into(),
+        Value::Datetime(d) => d.value().to_string().into(),
+        Value::Array(arr) => arr.iter().map(value_to_json).collect(),
+        Value::InlineTable(table) => table
+            .iter()
+            .map(|(key, value)| (key.to_string(), value_to_json(value)))
+            .collect::<serde_json::Map<_, _>>()
+            .into(),
+    }
+}
+
 fn print_installed_deps(project: &PyProject) -> Result<(), Error> {
     let python = get_venv_python_bin(&project.venv_path());
     if !python.is_file() {
@@ -89,3 +191,153 @@ fn print_installed_deps(project: &PyProject) -> Result<(), Error> {
 
     Ok(())
 }
+
+#[derive(Deserialize)]
+struct PypiProject {
+    info: PypiProjectInfo,
+}
+
+#[derive(Deserialize)]
+struct PypiProjectInfo {
+    version: String,
+}
+
+/// Prints the locked and latest versions of the direct dependencies.
+fn print_updates(project: &PyProject) -> Result<(), Error> {
+    let deps = project
+        .iter_dependencies(DependencyKind::Normal)
+        .filter_map(|dep| dep.expand(|_| Some("VARIABLE".into())).ok())
+        .filter(|req| !matches!(req.version_or_url, Some(VersionOrUrl::Url(_))))
+        .collect::<Vec<_>>();
+    if deps.is_empty() {
+        eprintln!("project has no dependencies");
+        return Ok(());
+    }
+
+    let lockfile = project.lockfile_path(false);
+    let mut locked = HashMap::new();
+    if lockfile.is_file() {
+        for req in read_locked_requirements(&lockfile)? {
+            if let Some(version) = pinned_version(&req) {
+                locked.insert(normalize_package_name(&req.name), version);
+            }
+        }
+    }
+
+    let latest = thread::scope(|s| {
+        let handles = deps
+            .iter()
+            .map(|req| s.spawn(|| find_latest_version(&req.name)))
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|x| x.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+
+    let rows = deps
+        .iter()
+        .zip(latest)
+        .map(|(req, latest)| {
+            let constraint = match req.version_or_url {
+                Some(VersionOrUrl::VersionSpecifier(ref specs)) => Some(specs),
+                _ => None,
+            };
+            let current = locked.get(&normalize_package_name(&req.name));
+            let status = match latest {
+                Ok(ref latest) if constraint.map_or(false, |x| !x.contains(latest)) => {
+                    style("outside constraint").red().to_string()
+                }
+                Ok(ref latest) if current == Some(latest) => style("✓").green().to_string(),
+                Ok(_) if current.is_none() => style("not locked").dim().to_string(),
+                Ok(_) => style("update available").yellow().to_string(),
+                Err(ref err) => style(format!("error: {}", err)).red().to_string(),
+            };
+            [
+                req.name.clone(),
+                constraint.map_or_else(|| "*".into(), |x| x.to_string()),
+                current.map_or_else(|| "-".into(), |x| x.to_string()),
+                latest.map_or_else(|_| "-".into(), |x| x.to_string()),
+                status,
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    let header = ["name", "constraint", "current", "latest", ""];
+    let mut widths = header.map(|x| x.len());
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(console::measure_text_width(cell));
+        }
+    }
+    println!(
+        "{:<w0$}  {:<w1$}  {:<w2$}  {}",
+        style(header[0]).bold(),
+        style(header[1]).bold(),
+        style(header[2]).bold(),
+        style(header[3]).bold(),
+        w0 = widths[0],
+        w1 = widths[1],
+        w2 = widths[2],
+    );
+    for row in &rows {
+        println!(
+            "{:<w0$}  {:<w1$}  {:<w2$}  {:<w3$}  {}",
+            row[0],
+            row[1],
+            row[2],
+            row[3],
+            row[4],
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2],
+            w3 = widths[3],
+        );
+    }
+    Ok(())
+}
+
+/// Returns the version a locked requirement is pinned to.
+fn pinned_version(req: &Requirement) -> Option<Version> {
+    match req.version_or_url {
+        Some(VersionOrUrl::VersionSpecifier(ref specs))
+            if specs.len() == 1 && *specs[0].operator() == Operator::Equal =>
+        {
+            Some(specs[0].version().clone())
+        }
+        _ => None,
+    }
+}
+
+/// Looks up the latest version of a package on PyPI.
+///
+/// The result is cached in the rye home folder for an hour.
+fn find_latest_version(name: &str) -> Result<Version, Error> {
+    let cache_file = get_app_dir()
+        .join("cache")
+        .join("latest-versions")
+        .join(normalize_package_name(name));
+    let is_fresh = fs::metadata(&cache_file)
+        .and_then(|x| x.modified())
+        .ok()
+        .and_then(|x| SystemTime::now().duration_since(x).ok())
+        .map_or(false, |age| age < UPDATES_CACHE_TTL);
+    if is_fresh {
+        if let Ok(version) = fs::read_to_string(&cache_file)
+            .map_err(Error::from)
+            .and_then(|x| Version::from_str(x.trim()).map_err(|err| anyhow!(err)))
+        {
+            return Ok(version);
+        }
+    }
+
+    let url = format!("https://pypi.org/pypi/{}/json", name);
+    let project: PypiProject = serde_json::from_slice(&download_url(&url, CommandOutput::Quiet)?)
+        .with_context(|| format!("invalid project info for {}", name))?;
+    let version = Version::from_str(&project.info.version).map_err(|err| anyhow!(err))?;
+    if let Some(parent) = cache_file.parent() {
+        fs::create_dir_all(parent).ok();
+    }
+    fs::write(&cache_file, version.to_string()).ok();
+    Ok(version)
+}
@@ -1,9 +1,46 @@
-use anyhow::Error;
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use anyhow::{bail, Context, Error};
 use clap::Parser;
+use console::style;
 
-use crate::lock::LockOptions;
+use crate::bootstrap::ensure_self_venv;
+use crate::cli::run::invoke_hook;
+use crate::lock::{read_locked_requirements, LockOptions};
+use crate::pyproject::{normalize_package_name, PyProject};
 use crate::sync::{sync, SyncMode, SyncOptions};
-use crate::utils::CommandOutput;
+use crate::utils::{find_site_packages, format_requirement, get_venv_python_bin, CommandOutput};
+
+const LIST_REQUIRES_SCRIPT: &str = r#"
+import re
+import sys
+import json
+
+if sys.version_info >= (3, 8):
+    from importlib.metadata import distributions
+else:
+    from importlib_metadata import distributions
+
+_package_re = re.compile('(?i)^([a-z0-9._-]+)')
+_extra_re = re.compile(r';.*\bextra\b')
+
+result = {}
+for dist in distributions():
+    req = []
+    for r in dist.requires or ():
+        # requirements of extras are only installed on request
+        if _extra_re.search(r) is not None:
+            continue
+        name = _package_re.match(r)
+        if name is not None:
+            req.append(name.group())
+    result[dist.metadata["Name"]] = req
+
+print(json.dumps(result))
+"#;
 
 /// Updates the virtualenv based on the pyproject.toml
 #[derive(Parser, Debug)]
@@ -14,9 +51,27 @@ pub struct Args {
     /// Do not include dev dependencies.
     #[arg(long)]
     no_dev: bool,
-    /// Do not update the lockfile.
+    /// Install from pyproject.toml without reading or writing the lockfiles.
     #[arg(long)]
     no_lock: bool,
+    /// Install from the lockfile and fail if it is out of date with pyproject.toml.
+    #[arg(long, conflicts_with_all = ["update", "update_all", "pre", "no_lock"])]
+    frozen: bool,
+    /// Compile the installed packages to bytecode after syncing.
+    #[arg(long)]
+    compile_bytecode: bool,
+    /// The optimization level for `--compile-bytecode` (like `python -O` and `python -OO`).
+    #[arg(long, requires = "compile_bytecode", value_parser = clap::value_parser!(u8).range(0..=2))]
+    optimize: Option<u8>,
+    /// Print the installed packages after syncing.
+    #[arg(long)]
+    show_packages: bool,
+    /// Print the installed packages as JSON.
+    #[arg(long, requires = "show_packages")]
+    json: bool,
+    /// Do not run the `post-sync` script.
+    #[arg(long)]
+    no_plugins: bool,
     /// Enables verbose diagnostics.
     #[arg(short, long)]
     verbose: bool,
@@ -33,18 +88,53 @@ pub struct Args {
     #[arg(long)]
     pre: bool,
     /// Extras/features to enable when synching the workspace.
-    #[arg(long)]
+    #[arg(long, visible_alias = "extra")]
     features: Vec<String>,
     /// Enables all features.
-    #[arg(long)]
+    #[arg(long, visible_alias = "all-extras")]
     all_features: bool,
+    /// Print which packages changed in the lockfiles.
+    #[arg(long)]
+    diff: bool,
+    /// Only install the main dependencies and these groups (`dev` selects the dev dependencies).
+    #[arg(
+        long,
+        value_delimiter = ',',
+        conflicts_with_all = ["no_dev", "features", "all_features"]
+    )]
+    groups: Vec<String>,
+    /// Only install the main dependencies (same as `--no-dev`).
+    #[arg(long, conflicts_with_all = ["groups", "features", "all_features"])]
+    no_groups: bool,
+    /// Force reinstall the given packages with their locked versions instead of syncing.
+    #[arg(long, conflicts_with_all = ["force", "update", "update_all"])]
+    reinstall: Vec<String>,
+    /// Also reinstall the installed packages that depend on the reinstalled packages.
+    #[arg(long, requires = "reinstall")]
+    reinstall_deps: bool,
 }
 
 pub fn execute(cmd: Args) -> Result<(), Error> {
     let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
+    let (dev, features) = if !cmd.groups.is_empty() {
+        let (dev, features): (Vec<_>, Vec<_>) = cmd
+            .groups
+            .into_iter()
+            .map(|x| x.trim().to_string())
+            .filter(|x| !x.is_empty())
+            .partition(|x| x == "dev");
+        (!dev.is_empty(), features)
+    } else {
+        (!cmd.no_dev && !cmd.no_groups, cmd.features)
+    };
+    if !cmd.reinstall.is_empty() {
+        return reinstall(&cmd.reinstall, dev, cmd.reinstall_deps, output);
+    }
+    let pyproject = PyProject::discover()?;
+    let installed_before = list_installed_packages(&pyproject.venv_path())?;
     sync(SyncOptions {
         output,
-        dev: !cmd.no_dev,
+        dev,
         mode: if cmd.force {
             SyncMode::Full
         } else {
@@ -52,13 +142,225 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
         },
         force: cmd.force,
         no_lock: cmd.no_lock,
+        frozen: cmd.frozen,
+        show_diff: cmd.diff,
+        dry_run: false,
+        check: false,
         lock_options: LockOptions {
             update: cmd.update,
             update_all: cmd.update_all,
             pre: cmd.pre,
-            features: cmd.features,
+            features,
             all_features: cmd.all_features,
+            ..LockOptions::default()
         },
     })?;
+    if cmd.compile_bytecode {
+        compile_bytecode(&pyproject.venv_path(), cmd.optimize.unwrap_or(0), output)?;
+    }
+    if !cmd.no_plugins {
+        let changed = installed_before != list_installed_packages(&pyproject.venv_path())?;
+        let env = vec![(
+            "RYE_SYNC_CHANGED".to_string(),
+            if changed { "1" } else { "0" }.to_string(),
+        )];
+        if let Some(status) = invoke_hook(&pyproject, "post-sync", env)? {
+            if !status.success() {
+                bail!("post-sync script failed with {}", status);
+            }
+        }
+    }
+    if cmd.show_packages && output != CommandOutput::Quiet {
+        show_packages(&pyproject.venv_path(), cmd.json, output)?;
+    }
+    Ok(())
+}
+
+/// Prints the packages installed in a virtualenv with `pip list`.
+fn show_packages(venv: &Path, json: bool, output: CommandOutput) -> Result<(), Error> {
+    let self_venv = ensure_self_venv(output).context("error bootstrapping venv")?;
+    let status = Command::new(get_venv_python_bin(&self_venv))
+        .arg("-mpip")
+        .arg("--python")
+        .arg(get_venv_python_bin(venv))
+        .arg("list")
+        .arg("--format")
+        .arg(if json { "json" } else { "columns" })
+        .env("PYTHONWARNINGS", "ignore")
+        .status()
+        .context("unable to run pip")?;
+    if !status.success() {
+        bail!("failed to list installed packages");
+    }
     Ok(())
 }
+
+/// Compiles the site-packages of a virtualenv to bytecode on all cores.
+///
+/// Files that fail to compile (like Python 2 only test files shipped by some
+/// packages) only cause a warning.
+fn compile_bytecode(venv: &Path, optimize: u8, output: CommandOutput) -> Result<(), Error> {
+    let site_packages = find_site_packages(venv)?;
+    if site_packages.is_empty() {
+        return Ok(());
+    }
+    if output != CommandOutput::Quiet {
+        eprintln!("Compiling bytecode");
+    }
+    let mut compile_cmd = Command::new(get_venv_python_bin(venv));
+    if optimize > 0 {
+        compile_cmd.arg(format!("-{}", "O".repeat(optimize as usize)));
+    }
+    compile_cmd
+        .arg("-mcompileall")
+        .arg("-q")
+        .arg("-j0")
+        .args(&site_packages);
+    if output != CommandOutput::Verbose {
+        compile_cmd.stdout(Stdio::null());
+    }
+    let status = compile_cmd.status().context("unable to compile bytecode")?;
+    if !status.success() && output != CommandOutput::Quiet {
+        eprintln!(
+            "{} some files could not be compiled to bytecode",
+            style("warning:").yellow()
+        );
+    }
+    Ok(())
+}
+
+/// Returns the names of the `.dist-info` folders in a virtualenv.
+///
+/// As those contain the version, this changes if any package was installed,
+/// removed or updated.
+fn list_installed_packages(venv: &Path) -> Result<BTreeSet<String>, Error> {
+    let mut rv = BTreeSet::new();
+    for site_packages in find_site_packages(venv)? {
+        for entry in fs::read_dir(site_packages)? {
+            let name = entry?.file_name().to_string_lossy().into_owned();
+            if name.ends_with(".dist-info") {
+                rv.insert(name);
+            }
+        }
+    }
+    Ok(rv)
+}
+
+/// Force reinstalls packages with the versions from the lockfile.
+fn reinstall(
+    packages: &[String],
+    dev: bool,
+    with_dependents: bool,
+    output: CommandOutput,
+) -> Result<(), Error> {
+    let pyproject = PyProject::discover()?;
+    let venv_python = get_venv_python_bin(&pyproject.venv_path());
+    if !venv_python.is_file() {
+        bail!("project virtualenv does not exist, run `rye sync` first");
+    }
+    let lockfile = pyproject.lockfile_path(dev);
+    if !lockfile.is_file() {
+        bail!(
+            "lockfile '{}' does not exist, run `rye sync` first",
+            lockfile.display()
+        );
+    }
+    let locked = read_locked_requirements(&lockfile)?;
+
+    let mut names = Vec::new();
+    for package in packages {
+        let name = normalize_package_name(package);
+        if !locked
+            .iter()
+            .any(|x| normalize_package_name(&x.name) == name)
+        {
+            bail!("package '{}' is not in {}", package, lockfile.display());
+        }
+        names.push(name);
+    }
+    if with_dependents {
+        for name in find_dependents(&venv_python, &names)? {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+
+    // dependents that are not locked (like the project itself) are left alone
+    let pinned = names
+        .iter()
+        .filter_map(|name| {
+            locked
+                .iter()
+                .find(|x| &normalize_package_name(&x.name) == name)
+        })
+        .map(|req| format_requirement(req).to_string())
+        .collect::<Vec<_>>();
+
+    let self_venv = ensure_self_venv(output).context("error bootstrapping venv")?;
+    let mut pip_cmd = Command::new(get_venv_python_bin(&self_venv));
+    pip_cmd
+        .arg("-mpip")
+        .arg("--python")
+        .arg(&venv_python)
+        .arg("install")
+        .arg("--force-reinstall")
+        .arg("--no-deps")
+        .env("PYTHONWARNINGS", "ignore");
+    if output == CommandOutput::Verbose {
+        pip_cmd.arg("--verbose");
+    } else if output == CommandOutput::Quiet {
+        pip_cmd.arg("-q");
+    }
+    pip_cmd.args(&pinned);
+
+    if output != CommandOutput::Quiet {
+        eprintln!("Reinstalling {}", pinned.join(", "));
+    }
+    let status = pip_cmd.status().context("unable to run pip")?;
+    if !status.success() {
+        bail!("failed to reinstall packages");
+    }
+    if output != CommandOutput::Quiet {
+        eprintln!("Done!");
+    }
+    Ok(())
+}
+
+/// Finds all installed packages that directly or indirectly depend on the
+/// given packages.
+fn find_dependents(venv_python: &Path, packages: &[String]) -> Result<Vec<String>, Error> {
+    let out = Command::new(venv_python)
+        .arg("-c")
+        .arg(LIST_REQUIRES_SCRIPT)
+        .stderr(Stdio::inherit())
+        .output()
+        .context("unable to list installed packages")?;
+    if !out.status.success() {
+        bail!("failed to list installed packages");
+    }
+    let requires: HashMap<String, Vec<String>> = serde_json::from_slice(&out.stdout)?;
+    let requires = requires
+        .into_iter()
+        .map(|(name, reqs)| {
+            let reqs = reqs
+                .iter()
+                .map(|x| normalize_package_name(x))
+                .collect::<Vec<_>>();
+            (normalize_package_name(&name), reqs)
+        })
+        .collect::<Vec<_>>();
+
+    let mut seen = packages.iter().cloned().collect::<BTreeSet<_>>();
+    let mut rv = Vec::new();
+    let mut to_resolve = packages.to_vec();
+    while let Some(package) = to_resolve.pop() {
+        for (name, reqs) in &requires {
+            if reqs.contains(&package) && seen.insert(name.clone()) {
+                rv.push(name.clone());
+                to_resolve.push(name.clone());
+            }
+        }
+    }
+    Ok(rv)
+}
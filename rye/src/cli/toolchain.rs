@@ -5,13 +5,15 @@ use std::fs;
 use std::path::PathBuf;
 use std::process::Command;
 
-use anyhow::{bail, Context, Error};
+use anyhow::{anyhow, bail, Context, Error};
 use clap::Parser;
 use console::style;
 use serde::Deserialize;
 
 use crate::platform::{get_canonical_py_path, list_known_toolchains};
-use crate::sources::{iter_downloadable, PythonVersion};
+use crate::sources::{
+    get_download_url, iter_downloadable, matches_version, PythonVersion, PythonVersionRequest,
+};
 use crate::utils::symlink_file;
 
 const INSPECT_SCRIPT: &str = r#"
@@ -57,6 +59,16 @@ pub struct RemoveCommand {
     version: String,
 }
 
+/// Shows the Python binary of an installed toolchain.
+#[derive(Parser, Debug)]
+pub struct ShowCommand {
+    /// The version of the toolchain (e.g. `3.11` or `pypy@3.9`).
+    version: String,
+    /// Print the toolchain's metadata as JSON.
+    #[arg(long)]
+    json: bool,
+}
+
 /// List all registered toolchains
 #[derive(Parser, Debug)]
 pub struct ListCommand {
@@ -71,6 +83,7 @@ enum SubCommand {
     List(ListCommand),
     Register(RegisterCommand),
     Remove(RemoveCommand),
+    Show(ShowCommand),
 }
 
 pub fn execute(cmd: Args) -> Result<(), Error> {
@@ -79,6 +92,7 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
         SubCommand::Fetch(args) => crate::cli::fetch::execute(args),
         SubCommand::List(args) => list(args),
         SubCommand::Remove(args) => remove(args),
+        SubCommand::Show(args) => show(args),
     }
 }
 
@@ -159,6 +173,35 @@ pub fn remove(cmd: RemoveCommand) -> Result<(), Error> {
     Ok(())
 }
 
+fn show(cmd: ShowCommand) -> Result<(), Error> {
+    let req: PythonVersionRequest = cmd.version.parse()?;
+    let (version, path) = list_known_toolchains()?
+        .into_iter()
+        .filter(|(version, _)| matches_version(&req, version))
+        .max_by(|a, b| a.0.cmp(&b.0))
+        .ok_or_else(|| anyhow!("toolchain {} is not installed", cmd.version))?;
+
+    if cmd.json {
+        // only downloaded toolchains have a known checksum
+        let sha256 = get_download_url(&version.clone().into(), OS, ARCH)
+            .filter(|x| x.0 == version)
+            .and_then(|x| x.2);
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&serde_json::json!({
+                "version": format!("{}.{}.{}", version.major, version.minor, version.patch),
+                "implementation": version.kind,
+                "path": path,
+                "arch": ARCH,
+                "sha256": sha256,
+            }))?
+        );
+    } else {
+        println!("{}", path.display());
+    }
+    Ok(())
+}
+
 fn list(cmd: ListCommand) -> Result<(), Error> {
     let mut toolchains = list_known_toolchains()?
         .into_iter()
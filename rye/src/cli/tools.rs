@@ -1,8 +1,15 @@
-use anyhow::Error;
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use anyhow::{bail, Context, Error};
 use clap::Parser;
 use console::style;
 
 use crate::installer::list_installed_tools;
+use crate::platform::get_app_dir;
+use crate::pyproject::{get_current_venv_python_version, normalize_package_name};
+use crate::utils::{find_site_packages, get_venv_python_bin};
 
 /// Helper utility to manage global tools.
 #[derive(Parser, Debug)]
@@ -19,12 +26,20 @@ pub struct ListCommand {
     include_scripts: bool,
 }
 
+/// Show information about an installed tool
+#[derive(Parser, Debug)]
+pub struct ShowCommand {
+    /// The name of the tool.
+    name: String,
+}
+
 #[derive(Parser, Debug)]
 #[allow(clippy::large_enum_variant)]
 enum SubCommand {
     Install(crate::cli::install::Args),
     Uninstall(crate::cli::uninstall::Args),
     List(ListCommand),
+    Show(ShowCommand),
 }
 
 pub fn execute(cmd: Args) -> Result<(), Error> {
@@ -32,6 +47,7 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
         SubCommand::Install(args) => crate::cli::install::execute(args),
         SubCommand::Uninstall(args) => crate::cli::uninstall::execute(args),
         SubCommand::List(args) => list_tools(args),
+        SubCommand::Show(args) => show_tool(args),
     }
 }
 
@@ -51,3 +67,78 @@ fn list_tools(cmd: ListCommand) -> Result<(), Error> {
 
     Ok(())
 }
+
+fn show_tool(cmd: ShowCommand) -> Result<(), Error> {
+    let name = normalize_package_name(&cmd.name);
+    let venv = get_app_dir().join("tools").join(&name);
+    if !venv.is_dir() {
+        bail!("tool '{}' is not installed", cmd.name);
+    }
+
+    let py = get_venv_python_bin(&venv);
+    let python_version = match get_current_venv_python_version(&venv) {
+        Some(version) => version.to_string(),
+        None => {
+            let out = Command::new(&py)
+                .arg("-c")
+                .arg("import platform; print(platform.python_version())")
+                .stdout(Stdio::piped())
+                .output()
+                .context("unable to determine Python version of the tool")?;
+            String::from_utf8_lossy(&out.stdout).trim().to_string()
+        }
+    };
+
+    let mut scripts = list_installed_tools()?.remove(&name).unwrap_or_default();
+    scripts.sort();
+
+    println!("{}", style(&name).cyan());
+    println!(
+        "  version: {}",
+        find_installed_version(&venv, &name)?
+            .as_deref()
+            .unwrap_or("unknown")
+    );
+    println!("  venv: {}", venv.display());
+    println!("  python: {} ({})", python_version, py.display());
+    if scripts.is_empty() {
+        println!("  scripts: {}", style("none").dim());
+    } else {
+        println!("  scripts:");
+    }
+    for script in scripts {
+        println!("    {}", script);
+    }
+
+    Ok(())
+}
+
+/// Finds the version of a package installed in a virtualenv by
+/// reading the `METADATA` file of its distribution.
+fn find_installed_version(venv: &Path, name: &str) -> Result<Option<String>, Error> {
+    for site_packages in find_site_packages(venv)? {
+        for entry in fs::read_dir(site_packages)? {
+            let path = entry?.path();
+            if path.extension().map_or(true, |x| x != "dist-info") {
+                continue;
+            }
+            let metadata = match fs::read_to_string(path.join("METADATA")) {
+                Ok(metadata) => metadata,
+                Err(_) => continue,
+            };
+            let mut dist_name = None;
+            let mut version = None;
+            for line in metadata.lines().take_while(|x| !x.is_empty()) {
+                if let Some(value) = line.strip_prefix("Name:") {
+                    dist_name = Some(normalize_package_name(value.trim()));
+                } else if let Some(value) = line.strip_prefix("Version:") {
+                    version = Some(value.trim().to_string());
+                }
+            }
+            if dist_name.as_deref() == Some(name) {
+                return Ok(version);
+            }
+        }
+    }
+    Ok(None)
+}
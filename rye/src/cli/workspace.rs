@@ -0,0 +1,212 @@
+use std::path::{Component, Path};
+use std::{env, fs};
+
+use anyhow::{anyhow, bail, Context, Error};
+use clap::Parser;
+use console::style;
+use minijinja::{context, Environment};
+
+use crate::config::Config;
+use crate::pyproject::PyProject;
+
+/// Minimal pyproject.toml template for new workspace members.
+const MEMBER_TOML_TEMPLATE: &str = r#"[project]
+name = {{ name }}
+version = "0.1.0"
+description = "Add a short description here"
+dependencies = []
+requires-python = {{ requires_python }}
+
+[build-system]
+requires = ["hatchling"]
+build-backend = "hatchling.build"
+
+[tool.rye]
+managed = true
+
+"#;
+
+/// Template for the __init__.py of new workspace members.
+const INIT_PY_TEMPLATE: &str = r#"def hello():
+    return "Hello from {{ name }}!"
+
+"#;
+
+/// Manages the members of a workspace.
+#[derive(Parser, Debug)]
+pub struct Args {
+    #[command(subcommand)]
+    command: SubCommand,
+}
+
+/// Adds a path as workspace member.
+#[derive(Parser, Debug)]
+pub struct AddCommand {
+    /// The path of the project to add.
+    path: String,
+}
+
+/// Removes a path from the workspace members.
+#[derive(Parser, Debug)]
+pub struct RemoveCommand {
+    /// The path of the project to remove.
+    path: String,
+}
+
+/// Lists all members of the workspace.
+#[derive(Parser, Debug)]
+pub struct ListCommand {}
+
+/// Creates a new project and adds it to the workspace.
+#[derive(Parser, Debug)]
+pub struct InitCommand {
+    /// The name of the new member (also used as folder name).
+    name: String,
+}
+
+#[derive(Parser, Debug)]
+enum SubCommand {
+    Add(AddCommand),
+    Remove(RemoveCommand),
+    List(ListCommand),
+    Init(InitCommand),
+}
+
+pub fn execute(cmd: Args) -> Result<(), Error> {
+    match cmd.command {
+        SubCommand::Add(args) => add(args),
+        SubCommand::Remove(args) => remove(args),
+        SubCommand::List(args) => list(args),
+        SubCommand::Init(args) => init(args),
+    }
+}
+
+/// Loads the pyproject.toml that declares (or will declare) the workspace.
+fn load_workspace_root() -> Result<PyProject, Error> {
+    let project = PyProject::discover()?;
+    if project.is_workspace_root() {
+        Ok(project)
+    } else {
+        PyProject::load(&project.workspace_path().join("pyproject.toml"))
+    }
+}
+
+/// Converts a path relative to the current directory into a member path
+/// relative to the workspace root.
+fn make_member_path(root: &PyProject, path: &str) -> Result<String, Error> {
+    let workspace_path = root.workspace_path();
+    let abs_path = env::current_dir()?.join(path);
+    let rel_path = pathdiff::diff_paths(&abs_path, &workspace_path).ok_or_else(|| {
+        anyhow!(
+            "unable to create relative path from {} to {}",
+            workspace_path.display(),
+            abs_path.display()
+        )
+    })?;
+    let mut rv = Vec::new();
+    for component in rel_path.components() {
+        match component {
+            Component::Normal(part) => rv.push(part.to_string_lossy().into_owned()),
+            Component::CurDir => {}
+            _ => bail!("'{}' is not inside the workspace", path),
+        }
+    }
+    if rv.is_empty() {
+        bail!("the workspace root is always a member of the workspace");
+    }
+    Ok(rv.join("/"))
+}
+
+fn add(cmd: AddCommand) -> Result<(), Error> {
+    let mut root = load_workspace_root()?;
+    let member = make_member_path(&root, &cmd.path)?;
+    if !root
+        .workspace_path()
+        .join(&member)
+        .join("pyproject.toml")
+        .is_file()
+    {
+        bail!("no pyproject.toml found in '{}'", cmd.path);
+    }
+    if root.add_workspace_member(&member)? {
+        root.save()?;
+        eprintln!("Added {} to workspace", style(&member).cyan());
+    } else {
+        eprintln!("{} is already a workspace member", style(&member).cyan());
+    }
+    Ok(())
+}
+
+fn remove(cmd: RemoveCommand) -> Result<(), Error> {
+    let mut root = load_workspace_root()?;
+    let member = make_member_path(&root, &cmd.path)?;
+    if root.remove_workspace_member(&member)? {
+        root.save()?;
+        eprintln!("Removed {} from workspace", style(&member).cyan());
+    } else {
+        bail!("'{}' is not a declared workspace member", member);
+    }
+    Ok(())
+}
+
+fn list(_cmd: ListCommand) -> Result<(), Error> {
+    let project = PyProject::discover()?;
+    let workspace = match project.workspace() {
+        Some(workspace) => workspace,
+        None => bail!("project is not part of a workspace"),
+    };
+    let mut projects = workspace.iter_projects().collect::<Result<Vec<_>, _>>()?;
+    projects.sort_by(|a, b| a.root_path().cmp(&b.root_path()));
+    for child in projects {
+        let root_path = child.root_path();
+        let rel_path = Path::new(".").join(
+            root_path
+                .strip_prefix(workspace.path())
+                .unwrap_or(&root_path),
+        );
+        println!(
+            "{} {} ({})",
+            style(child.name().unwrap_or("<unnamed>")).cyan(),
+            child.version().unwrap_or("<unversioned>"),
+            style(rel_path.display()).dim(),
+        );
+    }
+    Ok(())
+}
+
+fn init(cmd: InitCommand) -> Result<(), Error> {
+    let mut root = load_workspace_root()?;
+    let name = slug::slugify(cmd.name);
+    let member = make_member_path(&root, &root.workspace_path().join(&name).to_string_lossy())?;
+    let dir = root.workspace_path().join(&member);
+    let toml = dir.join("pyproject.toml");
+    if toml.is_file() {
+        bail!("pyproject.toml already exists in {}", dir.display());
+    }
+
+    let src_dir = dir.join("src").join(name.replace('-', "_"));
+    fs::create_dir_all(&src_dir)
+        .with_context(|| format!("failed to create {}", src_dir.display()))?;
+    let env = Environment::new();
+    let requires_python = Config::current().default_requires_python();
+    let rv = env.render_named_str(
+        "pyproject.json",
+        MEMBER_TOML_TEMPLATE,
+        context! { name, requires_python },
+    )?;
+    fs::write(&toml, rv).context("failed to write pyproject.toml")?;
+    let rv = env.render_named_str("__init__.py", INIT_PY_TEMPLATE, context! { name })?;
+    fs::write(src_dir.join("__init__.py"), rv).context("failed to write __init__.py")?;
+
+    root.add_workspace_member(&member)?;
+    root.save()?;
+
+    eprintln!(
+        "{} Initialized workspace member {} in {}",
+        style("success:").green(),
+        style(&name).cyan(),
+        dir.display()
+    );
+    eprintln!("  Run `rye sync` to get started");
+    Ok(())
+}
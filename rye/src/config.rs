@@ -87,4 +87,36 @@ impl Config {
         }
         .context("failed to get default toolchain")
     }
+
+    /// Returns the mirror that Python distributions are downloaded from.
+    pub fn fetch_mirror(&self) -> Option<String> {
+        self.doc
+            .get("behavior")
+            .and_then(|x| x.get("fetch"))
+            .and_then(|x| x.get("mirror"))
+            .and_then(|x| x.as_str())
+            .map(|x| x.to_string())
+    }
+
+    /// Returns the default package indexes used for locking.
+    ///
+    /// This is the `index-url` and the `extra-index-urls` of `[default.indexes]`.
+    pub fn default_indexes(&self) -> (Option<String>, Vec<String>) {
+        let indexes = self.doc.get("default").and_then(|x| x.get("indexes"));
+        let index_url = indexes
+            .and_then(|x| x.get("index-url"))
+            .and_then(|x| x.as_str())
+            .map(|x| x.to_string());
+        let extra_index_urls = indexes
+            .and_then(|x| x.get("extra-index-urls"))
+            .and_then(|x| x.as_array())
+            .map(|x| {
+                x.iter()
+                    .filter_map(|x| x.as_str())
+                    .map(|x| x.to_string())
+                    .collect()
+            })
+            .unwrap_or_default();
+        (index_url, extra_index_urls)
+    }
 }
@@ -16,7 +16,7 @@ use crate::consts::VENV_BIN;
 use crate::platform::get_app_dir;
 use crate::pyproject::normalize_package_name;
 use crate::sources::PythonVersionRequest;
-use crate::sync::create_virtualenv;
+use crate::sync::{create_virtualenv, VenvMarker};
 use crate::utils::{get_short_executable_name, get_venv_python_bin, symlink_file, CommandOutput};
 
 const FIND_SCRIPT_SCRIPT: &str = r#"
@@ -67,7 +67,7 @@ static SUCCESSFULLY_DOWNLOADED_RE: Lazy<Regex> =
 
 pub fn install(
     requirement: Requirement,
-    py_ver: &PythonVersionRequest,
+    py_ver: Option<&PythonVersionRequest>,
     force: bool,
     include_deps: &[String],
     output: CommandOutput,
@@ -88,27 +88,44 @@ pub fn install(
     let py = get_venv_python_bin(&target_venv_path);
     let target_venv_bin_path = target_venv_path.join(VENV_BIN);
 
+    // packages injected into the previous installation are injected again and
+    // unless another version is requested the previous python is reused.
+    let marker = read_tool_marker(&target_venv_path);
+    let injected = marker
+        .as_ref()
+        .map(|x| x.injected.clone())
+        .unwrap_or_default();
+    let py_ver = match (py_ver, marker) {
+        (Some(py_ver), _) => py_ver.clone(),
+        (None, Some(marker)) => marker.python.into(),
+        (None, None) => PythonVersionRequest {
+            kind: None,
+            major: 3,
+            minor: None,
+            patch: None,
+            suffix: None,
+        },
+    };
+
     uninstall_helper(&target_venv_path, &shim_dir)?;
 
     // make sure we have a compatible python version
-    let py_ver = fetch(py_ver, output)?;
+    let py_ver = fetch(&py_ver, output)?;
 
     create_virtualenv(output, &self_venv, &py_ver, &target_venv_path)?;
-
-    let mut cmd = Command::new(self_venv.join(VENV_BIN).join("pip"));
-    cmd.arg("--python")
-        .arg(&py)
-        .arg("install")
-        .env("PYTHONWARNINGS", "ignore");
-    if output == CommandOutput::Verbose {
-        cmd.arg("--verbose");
-    } else {
-        if output == CommandOutput::Quiet {
-            cmd.arg("-q");
-        }
-        cmd.env("PYTHONWARNINGS", "ignore");
-    }
+    // remember the requested toolchain so that it can be shown later
+    fs::write(
+        target_venv_path.join("rye-venv.json"),
+        serde_json::to_string_pretty(&VenvMarker {
+            python: py_ver.clone(),
+            injected: injected.clone(),
+        })?,
+    )
+    .context("failed writing venv marker file")?;
+
+    let mut cmd = pip_install_command(&self_venv, &py, output);
     cmd.arg("--").arg(&requirement.to_string());
+    cmd.args(&injected);
 
     // we don't support versions below 3.7, but for 3.7 we need importlib-metadata
     // to be installed
@@ -200,6 +217,78 @@ pub fn install(
     Ok(())
 }
 
+/// Installs extra packages into the virtualenv of an installed tool.
+///
+/// The packages are remembered and installed again when the tool is reinstalled.
+pub fn inject(tool: &str, packages: &[Requirement], output: CommandOutput) -> Result<(), Error> {
+    let self_venv = ensure_self_venv(output)?;
+    let target_venv_path = get_app_dir()
+        .join("tools")
+        .join(normalize_package_name(tool));
+    let mut marker = match read_tool_marker(&target_venv_path) {
+        Some(marker) if target_venv_path.is_dir() => marker,
+        _ => bail!(
+            "tool '{}' is not installed, install it first with `rye tools install {}`",
+            tool,
+            tool
+        ),
+    };
+
+    let mut cmd = pip_install_command(&self_venv, &get_venv_python_bin(&target_venv_path), output);
+    cmd.arg("--");
+    for package in packages {
+        cmd.arg(package.to_string());
+    }
+    let status = cmd.status()?;
+    if !status.success() {
+        bail!("failed to inject packages into {}", tool);
+    }
+
+    for package in packages {
+        let name = normalize_package_name(&package.name);
+        marker.injected.retain(|x| {
+            x.parse::<Requirement>()
+                .map_or(true, |x| normalize_package_name(&x.name) != name)
+        });
+        marker.injected.push(package.to_string());
+    }
+    fs::write(
+        target_venv_path.join("rye-venv.json"),
+        serde_json::to_string_pretty(&marker)?,
+    )
+    .context("failed writing venv marker file")?;
+
+    if output != CommandOutput::Quiet {
+        for package in packages {
+            eprintln!(
+                "Injected {} into {}",
+                style(package).cyan(),
+                style(tool).cyan()
+            );
+        }
+    }
+    Ok(())
+}
+
+fn read_tool_marker(target_venv_path: &Path) -> Option<VenvMarker> {
+    let contents = fs::read(target_venv_path.join("rye-venv.json")).ok()?;
+    serde_json::from_slice(&contents).ok()
+}
+
+fn pip_install_command(self_venv: &Path, py: &Path, output: CommandOutput) -> Command {
+    let mut cmd = Command::new(self_venv.join(VENV_BIN).join("pip"));
+    cmd.arg("--python")
+        .arg(py)
+        .arg("install")
+        .env("PYTHONWARNINGS", "ignore");
+    if output == CommandOutput::Verbose {
+        cmd.arg("--verbose");
+    } else if output == CommandOutput::Quiet {
+        cmd.arg("-q");
+    }
+    cmd
+}
+
 fn find_scripts(files: &[PathBuf], target_venv_bin_path: &Path) -> Vec<String> {
     let mut rv = Vec::new();
     for file in files {
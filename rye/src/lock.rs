@@ -1,26 +1,38 @@
-use std::borrow::Cow;
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::io::{BufWriter, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::sync::Arc;
-use std::{fmt, fs};
+use std::{fmt, fs, thread};
 
 use anyhow::{anyhow, bail, Context, Error};
+use console::style;
 use minijinja::render;
 use once_cell::sync::Lazy;
-use pep508_rs::Requirement;
+use pep440_rs::Operator;
+use pep508_rs::{Requirement, VersionOrUrl};
 use regex::Regex;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use tempfile::NamedTempFile;
 use url::Url;
 
+use crate::bootstrap::download_url;
+use crate::config::Config;
 use crate::piptools::get_pip_compile;
-use crate::pyproject::{normalize_package_name, DependencyKind, PyProject, Workspace};
+use crate::platform::get_index_netrc;
+use crate::pyproject::{
+    normalize_package_name, DependencyKind, DependencyRef, PyProject, Workspace,
+};
 use crate::sources::PythonVersion;
-use crate::utils::{get_venv_python_bin, CommandOutput};
+use crate::utils::{get_venv_python_bin, strip_url_credentials, CommandOutput};
 
 static FILE_EDITABLE_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^-e (file://.*?)\s*$").unwrap());
+static LOCK_FLAG_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?m)^#   ([a-z-]+): (.*?)\s*$").unwrap());
+static VIA_REQUIREMENTS_IN_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"-r \S+").unwrap());
+static PYPROJECT_HASH_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?m)^# pyproject-hash: ([0-9a-f]+)\s*$").unwrap());
 static REQUIREMENTS_HEADER: &str = r#"# generated by rye
 # use `rye lock` or `rye sync` to update this lockfile
 # 
@@ -28,6 +40,11 @@ static REQUIREMENTS_HEADER: &str = r#"# generated by rye
 #   pre: {{ lock_options.pre }}
 #   features: {{ lock_options.features }}
 #   all-features: {{ lock_options.all_features }}
+#   with-sources: {{ lock_options.with_sources }}
+#   annotate: {{ lock_options.annotate }}
+#   resolution-mode: {{ lock_options.resolution_mode }}
+#
+# pyproject-hash: {{ pyproject_hash }}
 
 "#;
 
@@ -50,6 +67,57 @@ impl fmt::Display for LockMode {
     }
 }
 
+/// The resolver algorithm pip-compile uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum LockStrategy {
+    /// pip's current resolver which backtracks on conflicts.
+    #[default]
+    Backtracking,
+    /// pip's old resolver, which is faster but can produce inconsistent pins.
+    Legacy,
+}
+
+impl fmt::Display for LockStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LockStrategy::Backtracking => "backtracking",
+            LockStrategy::Legacy => "legacy",
+        })
+    }
+}
+
+/// Which versions the resolver picks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum ResolutionMode {
+    /// The highest versions of all packages.
+    #[default]
+    Highest,
+    /// The lowest versions of direct dependencies, the highest of all others.
+    LowestDirect,
+}
+
+impl fmt::Display for ResolutionMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ResolutionMode::Highest => "highest",
+            ResolutionMode::LowestDirect => "lowest-direct",
+        })
+    }
+}
+
+/// A dependency that is locked without being written to a `pyproject.toml`.
+///
+/// It replaces a dependency of the same name and kind of the project.
+#[derive(Debug, Clone)]
+pub struct ExtraDependency {
+    /// The normalized name of the project the dependency belongs to.
+    pub project: String,
+    pub kind: DependencyKind<'static>,
+    pub dep: DependencyRef,
+}
+
 /// Controls how locking should work.
 #[derive(Debug, Clone, Default, Serialize)]
 pub struct LockOptions {
@@ -63,6 +131,28 @@ pub struct LockOptions {
     pub features: Vec<String>,
     /// Enable all features in the workspace.
     pub all_features: bool,
+    /// Record the sdist URL of every locked package (defaults to what the lockfile was generated with).
+    pub with_sources: Option<bool>,
+    /// Resolve against this index instead of PyPI.
+    pub index_url: Option<String>,
+    /// Additional indexes to resolve against.
+    pub extra_index_urls: Vec<String>,
+    /// Indexes that are added on top of the other ones, like `tool.rye.sources`.
+    pub sources: Vec<String>,
+    /// Dependencies to lock as if they were declared in the `pyproject.toml` files.
+    #[serde(skip)]
+    pub extra_dependencies: Vec<ExtraDependency>,
+    /// The interpreter to resolve for (defaults to the one of the virtualenv).
+    #[serde(skip)]
+    pub python: Option<PathBuf>,
+    /// The resolver algorithm to use.
+    pub strategy: LockStrategy,
+    /// Which versions the resolver picks (defaults to the one the lockfile was generated with).
+    pub resolution_mode: Option<ResolutionMode>,
+    /// Packages to leave out of the lockfiles.
+    pub exclude: Vec<String>,
+    /// Note below every package which packages require it (defaults to what the lockfile was generated with).
+    pub annotate: Option<bool>,
 }
 
 /// Creates lockfiles for all projects in the workspace.
@@ -77,6 +167,7 @@ pub fn update_workspace_lockfile(
     if output != CommandOutput::Quiet {
         eprintln!("Generating {} lockfile: {}", lock_mode, lockfile.display());
     }
+    let lock_options = &restore_lock_options(lockfile, lock_options)?;
 
     let features_by_project = collect_workspace_features(lock_options);
     let mut req_file = NamedTempFile::new()?;
@@ -99,18 +190,34 @@ pub fn update_workspace_lockfile(
             &local_projects,
             req_file.as_file_mut(),
             DependencyKind::Normal,
+            lock_options,
         )?;
+        for group in get_project_dependency_groups(features_by_project.as_ref(), pyproject)? {
+            dump_dependencies(
+                pyproject,
+                &local_projects,
+                req_file.as_file_mut(),
+                DependencyKind::Group(group.into()),
+                lock_options,
+            )?;
+        }
         if lock_mode == LockMode::Dev {
             dump_dependencies(
                 pyproject,
                 &local_projects,
                 req_file.as_file_mut(),
                 DependencyKind::Dev,
+                lock_options,
             )?;
         }
     }
 
-    let exclusions = find_exclusions(&projects)?;
+    let exclusions = find_exclusions(&projects, lock_options)?;
+    let pyproject_hash = hash_pyproject_files(&projects)?;
+    let sources = projects
+        .iter()
+        .flat_map(|x| x.sources())
+        .collect::<Vec<_>>();
     generate_lockfile(
         output,
         py_ver,
@@ -119,6 +226,8 @@ pub fn update_workspace_lockfile(
         lockfile,
         lock_options,
         &exclusions,
+        &pyproject_hash,
+        &sources,
         &[],
     )?;
     generate_lockfile(
@@ -129,17 +238,53 @@ pub fn update_workspace_lockfile(
         lockfile,
         lock_options,
         &exclusions,
+        &pyproject_hash,
+        &sources,
         &["--pip-args=--no-deps"],
     )?;
 
     Ok(())
 }
 
-fn format_project_extras<'a>(
-    features_by_project: Option<&'a HashMap<String, HashSet<&str>>>,
+fn format_project_extras(
+    features_by_project: Option<&HashMap<String, HashSet<&str>>>,
+    project: &PyProject,
+) -> Result<String, Error> {
+    // dependency groups are locked separately and are not extras
+    let extras = project.extras();
+    let groups = project.dependency_groups();
+    let features: Vec<_> = get_project_features(features_by_project, project)?
+        .into_iter()
+        .filter(|x| extras.contains(x.as_str()) || !groups.contains(x.as_str()))
+        .collect();
+    Ok(if features.is_empty() {
+        "".into()
+    } else {
+        format!("[{}]", features.join(","))
+    })
+}
+
+/// Returns the dependency groups of a project that should be locked.
+fn get_project_dependency_groups(
+    features_by_project: Option<&HashMap<String, HashSet<&str>>>,
     project: &PyProject,
-) -> Result<Cow<'a, str>, Error> {
-    let features: Vec<_> = match features_by_project {
+) -> Result<Vec<String>, Error> {
+    let groups = project.dependency_groups();
+    Ok(match features_by_project {
+        Some(_) => get_project_features(features_by_project, project)?
+            .into_iter()
+            .filter(|x| groups.contains(x.as_str()))
+            .collect(),
+        None => groups.into_iter().map(|x| x.to_string()).collect(),
+    })
+}
+
+/// Returns the requested features for a project.
+fn get_project_features(
+    features_by_project: Option<&HashMap<String, HashSet<&str>>>,
+    project: &PyProject,
+) -> Result<Vec<String>, Error> {
+    let mut features: Vec<String> = match features_by_project {
         Some(features_by_project) => features_by_project
             .get(&project.normalized_name()?)
             .map_or(None.into_iter(), |x| Some(x.iter().copied()).into_iter())
@@ -153,14 +298,13 @@ fn format_project_extras<'a>(
                 .into_iter()
                 .flatten()
             })
+            .map(|x| x.to_string())
             .collect(),
-        None => project.extras().iter().copied().collect(),
+        None => project.extras().iter().map(|x| x.to_string()).collect(),
     };
-    Ok(if features.is_empty() {
-        Cow::Borrowed("")
-    } else {
-        Cow::Owned(format!("[{}]", features.join(",")))
-    })
+    features.sort();
+    features.dedup();
+    Ok(features)
 }
 
 fn collect_workspace_features(
@@ -191,10 +335,20 @@ fn collect_workspace_features(
     Some(features_by_project)
 }
 
-fn find_exclusions(projects: &[PyProject]) -> Result<HashSet<Requirement>, Error> {
+fn find_exclusions(
+    projects: &[PyProject],
+    lock_options: &LockOptions,
+) -> Result<HashSet<Requirement>, Error> {
     let mut rv = HashSet::new();
     for project in projects {
-        for dep in project.iter_dependencies(DependencyKind::Excluded) {
+        for name in project.lock_exclude() {
+            rv.insert(
+                name.parse::<Requirement>().with_context(|| {
+                    format!("invalid package '{}' in tool.rye.lock.exclude", name)
+                })?,
+            );
+        }
+        for dep in get_dependencies(project, DependencyKind::Excluded, lock_options) {
             rv.insert(dep.expand(|name: &str| {
                 if name == "PROJECT_ROOT" {
                     Some(project.workspace_path().to_string_lossy().to_string())
@@ -204,6 +358,12 @@ fn find_exclusions(projects: &[PyProject]) -> Result<HashSet<Requirement>, Error
             })?);
         }
     }
+    for name in &lock_options.exclude {
+        rv.insert(
+            name.parse::<Requirement>()
+                .with_context(|| format!("invalid package '{}' passed to --exclude", name))?,
+        );
+    }
     Ok(rv)
 }
 
@@ -212,8 +372,10 @@ fn dump_dependencies(
     local_projects: &HashMap<String, String>,
     out: &mut fs::File,
     dep_kind: DependencyKind,
+    lock_options: &LockOptions,
 ) -> Result<(), Error> {
-    for dep in pyproject.iter_dependencies(dep_kind) {
+    let editables = pyproject.editable_dependencies();
+    for dep in get_dependencies(pyproject, dep_kind, lock_options) {
         if let Ok(expanded_dep) = dep.expand(|_| {
             // we actually do not care what it expands to much, for as long
             // as the end result parses
@@ -230,11 +392,121 @@ fn dump_dependencies(
                 continue;
             }
         }
-        writeln!(out, "{}", dep)?;
+        write_dependency(out, &dep, &editables, lock_options)?;
     }
     Ok(())
 }
 
+/// Returns the dependencies of a project with the extra dependencies applied.
+fn get_dependencies(
+    pyproject: &PyProject,
+    kind: DependencyKind,
+    lock_options: &LockOptions,
+) -> Vec<DependencyRef> {
+    let extra = get_extra_dependencies(pyproject, &kind, lock_options).collect::<Vec<_>>();
+    let replaced = extra
+        .iter()
+        .filter_map(|x| get_dependency_name(x))
+        .collect::<HashSet<_>>();
+    pyproject
+        .iter_dependencies(kind.clone())
+        .filter(|dep| get_dependency_name(dep).map_or(true, |x| !replaced.contains(&x)))
+        .chain(extra.into_iter().cloned())
+        .collect()
+}
+
+/// Returns the extra dependencies of a given kind for a project.
+fn get_extra_dependencies<'a>(
+    pyproject: &PyProject,
+    kind: &'a DependencyKind,
+    lock_options: &'a LockOptions,
+) -> impl Iterator<Item = &'a DependencyRef> + 'a {
+    let project = pyproject.name().map(normalize_package_name);
+    lock_options
+        .extra_dependencies
+        .iter()
+        .filter(move |x| Some(&x.project) == project.as_ref() && &x.kind == kind)
+        .map(|x| &x.dep)
+}
+
+fn get_dependency_name(dep: &DependencyRef) -> Option<String> {
+    dep.expand(|_| Some("VARIABLE".into()))
+        .ok()
+        .map(|x| normalize_package_name(&x.name))
+}
+
+/// Writes a dependency into the requirements file handed to pip-compile.
+///
+/// In the `lowest-direct` resolution mode, the dependency is additionally
+/// pinned to the lower bound of its version specifier.
+fn write_dependency<W: Write>(
+    out: &mut W,
+    dep: &DependencyRef,
+    editables: &HashSet<String>,
+    lock_options: &LockOptions,
+) -> Result<(), Error> {
+    writeln!(out, "{}", format_dependency(dep, editables))?;
+    if lock_options.resolution_mode == Some(ResolutionMode::LowestDirect) {
+        if let Some(pin) = format_lowest_pin(dep) {
+            writeln!(out, "{}", pin)?;
+        }
+    }
+    Ok(())
+}
+
+/// Pins a dependency to the lower bound of its version specifier.
+///
+/// Dependencies without a lower bound (or with a URL) are not pinned.  The
+/// marker is kept so that the pin only applies where the dependency does.
+fn format_lowest_pin(dep: &DependencyRef) -> Option<String> {
+    let mut req = dep.expand(|_| Some("VARIABLE".into())).ok()?;
+    let lower_bound = match req.version_or_url {
+        Some(VersionOrUrl::VersionSpecifier(ref specs)) => specs
+            .iter()
+            .filter(|spec| {
+                matches!(
+                    spec.operator(),
+                    Operator::GreaterThanEqual
+                        | Operator::TildeEqual
+                        | Operator::Equal
+                        | Operator::ExactEqual
+                )
+            })
+            .map(|spec| spec.version().clone())
+            .max()?,
+        _ => return None,
+    };
+    req.extras = None;
+    req.version_or_url = Some(VersionOrUrl::VersionSpecifier(
+        format!("=={}", lower_bound).parse().ok()?,
+    ));
+    Some(req.to_string())
+}
+
+/// Formats a dependency for the requirements file handed to pip-compile.
+///
+/// Path dependencies marked as editable are written as `-e` lines so that
+/// they are installed in development mode.
+fn format_dependency(dep: &DependencyRef, editables: &HashSet<String>) -> String {
+    let raw = dep.to_string();
+    if let Ok(req) = dep.expand(|_| Some("VARIABLE".into())) {
+        if editables.contains(&normalize_package_name(&req.name)) {
+            if let Some((_, url)) = raw.split_once('@') {
+                let url = url.split(';').next().unwrap_or_default().trim();
+                if url.starts_with("file:") {
+                    return match req.extras {
+                        Some(ref extras) if !extras.is_empty() => {
+                            format!("-e {}[{}]", url, extras.join(","))
+                        }
+                        _ => format!("-e {}", url),
+                    };
+                }
+            }
+        }
+    }
+    raw
+}
+
 /// Updates the lockfile of the current project.
 pub fn update_single_project_lockfile(
     py_ver: &PythonVersion,
@@ -247,6 +519,7 @@ pub fn update_single_project_lockfile(
     if output != CommandOutput::Quiet {
         eprintln!("Generating {} lockfile: {}", lock_mode, lockfile.display());
     }
+    let lock_options = &restore_lock_options(lockfile, lock_options)?;
 
     let features_by_project = collect_workspace_features(lock_options);
     let applicable_extras = format_project_extras(features_by_project.as_ref(), pyproject)?;
@@ -257,16 +530,35 @@ pub fn update_single_project_lockfile(
         make_relative_url(&pyproject.root_path(), &pyproject.workspace_path())?,
         applicable_extras
     )?;
-    for dep in pyproject.iter_dependencies(DependencyKind::Normal) {
-        writeln!(req_file, "{}", dep)?;
+    let editables = pyproject.editable_dependencies();
+    for dep in get_dependencies(pyproject, DependencyKind::Normal, lock_options) {
+        write_dependency(&mut req_file, &dep, &editables, lock_options)?;
+    }
+    for group in get_project_dependency_groups(features_by_project.as_ref(), pyproject)? {
+        for dep in get_dependencies(pyproject, DependencyKind::Group(group.into()), lock_options) {
+            write_dependency(&mut req_file, &dep, &editables, lock_options)?;
+        }
     }
     if lock_mode == LockMode::Dev {
-        for dep in pyproject.iter_dependencies(DependencyKind::Dev) {
-            writeln!(req_file, "{}", dep)?;
+        for dep in get_dependencies(pyproject, DependencyKind::Dev, lock_options) {
+            write_dependency(&mut req_file, &dep, &editables, lock_options)?;
+        }
+    }
+    // optional dependencies are picked up from the project's metadata, only
+    // the extra ones have to be passed explicitly.
+    for feature in get_project_features(features_by_project.as_ref(), pyproject)? {
+        for dep in get_extra_dependencies(
+            pyproject,
+            &DependencyKind::Optional(feature.into()),
+            lock_options,
+        ) {
+            write_dependency(&mut req_file, dep, &editables, lock_options)?;
         }
     }
 
-    let exclusions = find_exclusions(std::slice::from_ref(pyproject))?;
+    let exclusions = find_exclusions(std::slice::from_ref(pyproject), lock_options)?;
+    let pyproject_hash = hash_pyproject_files(std::slice::from_ref(pyproject))?;
+    let sources = pyproject.sources();
     generate_lockfile(
         output,
         py_ver,
@@ -275,6 +567,8 @@ pub fn update_single_project_lockfile(
         lockfile,
         lock_options,
         &exclusions,
+        &pyproject_hash,
+        &sources,
         &[],
     )?;
 
@@ -290,32 +584,70 @@ fn generate_lockfile(
     lockfile: &Path,
     lock_options: &LockOptions,
     exclusions: &HashSet<Requirement>,
+    pyproject_hash: &str,
+    sources: &[String],
     extra_args: &[&str],
 ) -> Result<(), Error> {
     let scratch = tempfile::tempdir()?;
     let requirements_file = scratch.path().join("requirements.txt");
-    if lockfile.is_file() {
+    // when updating everything or changing the resolution mode, start from
+    // scratch rather than from the old pins
+    if lockfile.is_file()
+        && !lock_options.update_all
+        && Some(read_lockfile_resolution_mode(lockfile)?) == lock_options.resolution_mode
+    {
         fs::copy(lockfile, &requirements_file)?;
     } else {
         fs::write(&requirements_file, b"")?;
     }
 
+    let (index_url, extra_index_urls) = resolve_indexes(lockfile, lock_options, sources)?;
+    let netrc = get_index_netrc(
+        &index_url
+            .iter()
+            .chain(extra_index_urls.iter())
+            .cloned()
+            .collect::<Vec<_>>(),
+    )?;
+    let on_pypi = index_url.as_deref().map_or(true, is_pypi_index);
     let pip_compile = get_pip_compile(py_ver, output)?;
     let mut cmd = Command::new(pip_compile);
-    cmd.arg("--resolver=backtracking")
-        .arg("--no-annotate")
+    cmd.arg(format!("--resolver={}", lock_options.strategy))
+        .arg(if lock_options.annotate == Some(true) {
+            "--annotate"
+        } else {
+            "--no-annotate"
+        })
         .arg("--strip-extras")
         .arg("--allow-unsafe")
         .arg("--no-header")
         .arg("--pip-args")
         .arg(format!(
             "--python=\"{}\"",
-            get_venv_python_bin(&workspace_path.join(".venv")).display()
+            lock_options
+                .python
+                .clone()
+                .unwrap_or_else(|| get_venv_python_bin(&workspace_path.join(".venv")))
+                .display()
         ))
         .arg("-o")
         .arg(&requirements_file)
         .arg(requirements_file_in)
+        // path dependencies refer to ${PROJECT_ROOT}, see sync
+        .env(
+            "PROJECT_ROOT",
+            workspace_path.to_string_lossy().replace(' ', "%2F"),
+        )
         .env("PYTHONWARNINGS", "ignore");
+    if let Some(ref netrc) = netrc {
+        cmd.env("NETRC", netrc.path());
+    }
+    if let Some(index_url) = index_url {
+        cmd.arg("--index-url").arg(index_url);
+    }
+    for extra_index_url in extra_index_urls {
+        cmd.arg("--extra-index-url").arg(extra_index_url);
+    }
     if output == CommandOutput::Verbose {
         cmd.arg("--verbose");
     } else {
@@ -337,27 +669,130 @@ fn generate_lockfile(
         bail!("failed to generate lockfile");
     };
 
+    // the sdists are looked up with the PyPI JSON API which other indexes
+    // do not necessarily provide
+    let sdists = if lock_options.with_sources != Some(true) {
+        HashMap::new()
+    } else if on_pypi {
+        find_sdists(&read_locked_requirements(&requirements_file)?, output)
+    } else {
+        if output != CommandOutput::Quiet {
+            eprintln!(
+                "{} not recording sdists, they can only be looked up on PyPI",
+                style("warning:").yellow()
+            );
+        }
+        HashMap::new()
+    };
+
     finalize_lockfile(
         &requirements_file,
         lockfile,
         workspace_path,
         exclusions,
         lock_options,
+        pyproject_hash,
+        &sdists,
     )?;
 
     Ok(())
 }
 
+/// Picks the package indexes to lock against.
+///
+/// Explicitly passed indexes win, then the ones the lockfile was generated
+/// with and finally the ones from the config.  The `tool.rye.sources` of the
+/// projects and the passed sources are always added as extra indexes.
+fn resolve_indexes(
+    lockfile: &Path,
+    lock_options: &LockOptions,
+    sources: &[String],
+) -> Result<(Option<String>, Vec<String>), Error> {
+    let (index_url, mut extra_index_urls) =
+        if lock_options.index_url.is_some() || !lock_options.extra_index_urls.is_empty() {
+            (
+                lock_options.index_url.clone(),
+                lock_options.extra_index_urls.clone(),
+            )
+        } else {
+            match read_lockfile_indexes(lockfile)? {
+                (None, urls) if urls.is_empty() => Config::current().default_indexes(),
+                indexes => indexes,
+            }
+        };
+    for source in sources.iter().chain(lock_options.sources.iter()) {
+        if index_url.as_ref() != Some(source) && !extra_index_urls.contains(source) {
+            extra_index_urls.push(source.clone());
+        }
+    }
+    Ok((index_url, extra_index_urls))
+}
+
+/// Reads the `--index-url` and `--extra-index-url` lines pip-compile places
+/// into a lockfile.
+///
+/// A missing lockfile has no indexes.
+pub fn read_lockfile_indexes(lockfile: &Path) -> Result<(Option<String>, Vec<String>), Error> {
+    let mut index_url = None;
+    let mut extra_index_urls = Vec::new();
+    if !lockfile.is_file() {
+        return Ok((index_url, extra_index_urls));
+    }
+    for line in fs::read_to_string(lockfile)?.lines() {
+        if let Some(url) = line.strip_prefix("--index-url ") {
+            index_url = Some(url.trim().to_string());
+        } else if let Some(url) = line.strip_prefix("--extra-index-url ") {
+            extra_index_urls.push(url.trim().to_string());
+        }
+    }
+    Ok((index_url, extra_index_urls))
+}
+
 fn finalize_lockfile(
     generated: &Path,
     out: &Path,
     workspace_root: &Path,
     exclusions: &HashSet<Requirement>,
     lock_options: &LockOptions,
+    pyproject_hash: &str,
+    sdists: &HashMap<String, (String, String)>,
 ) -> Result<(), Error> {
+    let generated = fs::read_to_string(generated)?;
+    let vias = parse_vias(&generated);
+    let mut current = None;
     let mut rv = BufWriter::new(fs::File::create(out)?);
-    writeln!(rv, "{}", render!(REQUIREMENTS_HEADER, lock_options))?;
-    for line in fs::read_to_string(generated)?.lines() {
+    writeln!(
+        rv,
+        "{}",
+        render!(REQUIREMENTS_HEADER, lock_options, pyproject_hash)
+    )?;
+    for line in generated.lines() {
+        if line.starts_with("    #") && lock_options.annotate == Some(true) {
+            // pip-compile only names the immediate parents, replace them with
+            // the chains that lead to them from a direct dependency.
+            if let Some(parents) = current.as_ref().and_then(|x| vias.get(x)) {
+                if line.starts_with("    # via") {
+                    write_via_chains(&mut rv, parents, &vias)?;
+                }
+                continue;
+            }
+            // direct dependencies are annotated with the temporary requirements
+            // file we generated from the pyproject.toml files.
+            writeln!(
+                rv,
+                "{}",
+                VIA_REQUIREMENTS_IN_RE.replace_all(line, "-r pyproject.toml")
+            )?;
+            continue;
+        }
+        current = None;
+        // pip-compile emits the indexes, never write their credentials
+        if let Some((flag, url)) = line.split_once(' ').filter(|x| x.0.ends_with("index-url")) {
+            if let Ok(url) = Url::parse(url.trim()) {
+                writeln!(rv, "{} {}", flag, strip_url_credentials(&url))?;
+                continue;
+            }
+        }
         if let Some(m) = FILE_EDITABLE_RE.captures(line) {
             let url = Url::parse(&m[1]).context("invalid editable URL generated")?;
             if url.scheme() == "file" {
@@ -366,6 +801,7 @@ fn finalize_lockfile(
                 continue;
             }
         } else if let Ok(ref req) = line.trim().parse::<Requirement>() {
+            current = Some(normalize_package_name(&req.name));
             // TODO: this does not evaluate markers
             if exclusions.iter().any(|x| {
                 normalize_package_name(&x.name) == normalize_package_name(&req.name)
@@ -375,12 +811,276 @@ fn finalize_lockfile(
                 writeln!(rv, "# excluded {}", line)?;
                 continue;
             }
+            if let Some((url, sha256)) = sdists.get(&normalize_package_name(&req.name)) {
+                writeln!(rv, "{}", line)?;
+                writeln!(rv, "    # sdist: {}#sha256={}", url, sha256)?;
+                continue;
+            }
         }
         writeln!(rv, "{}", line)?;
     }
     Ok(())
 }
 
+/// Collects the parents pip-compile annotated every package with.
+fn parse_vias(generated: &str) -> HashMap<String, Vec<String>> {
+    let mut rv = HashMap::new();
+    let mut current: Option<String> = None;
+    for line in generated.lines() {
+        if let Some(comment) = line.strip_prefix("    #") {
+            let parent = match comment.strip_prefix(" via") {
+                Some(rest) => rest.trim(),
+                None => comment.trim(),
+            };
+            if let (Some(name), false) = (&current, parent.is_empty()) {
+                rv.entry(name.clone())
+                    .or_insert_with(Vec::new)
+                    .push(parent.to_string());
+            }
+        } else {
+            current = line
+                .trim()
+                .parse::<Requirement>()
+                .ok()
+                .map(|req| normalize_package_name(&req.name));
+        }
+    }
+    rv
+}
+
+/// Finds the shortest chain of packages from a direct dependency to `name`.
+fn find_via_chain(name: &str, vias: &HashMap<String, Vec<String>>) -> Vec<String> {
+    let mut seen = HashSet::from([normalize_package_name(name)]);
+    let mut queue = VecDeque::from([vec![name.to_string()]]);
+    while let Some(chain) = queue.pop_front() {
+        let parents = match vias.get(&normalize_package_name(&chain[0])) {
+            Some(parents) if !parents.iter().any(|x| x.starts_with('-')) => parents,
+            // direct dependency or unknown parents
+            _ => return chain,
+        };
+        for parent in parents {
+            if seen.insert(normalize_package_name(parent)) {
+                let mut next = vec![parent.clone()];
+                next.extend(chain.iter().cloned());
+                queue.push_back(next);
+            }
+        }
+    }
+    vec![name.to_string()]
+}
+
+/// Writes the `# via` comment of a package in pip-compile's format.
+fn write_via_chains<W: Write>(
+    w: &mut W,
+    parents: &[String],
+    vias: &HashMap<String, Vec<String>>,
+) -> Result<(), Error> {
+    let chains = parents
+        .iter()
+        .map(|parent| {
+            if parent.starts_with("-r ") {
+                "-r pyproject.toml".to_string()
+            } else if parent.starts_with('-') {
+                parent.clone()
+            } else {
+                find_via_chain(parent, vias).join(" -> ")
+            }
+        })
+        .collect::<Vec<_>>();
+    if let [chain] = &chains[..] {
+        writeln!(w, "    # via {}", chain)?;
+    } else {
+        writeln!(w, "    # via")?;
+        for chain in chains {
+            writeln!(w, "    #   {}", chain)?;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct PypiRelease {
+    urls: Vec<PypiReleaseFile>,
+}
+
+#[derive(Deserialize)]
+struct PypiReleaseFile {
+    packagetype: String,
+    url: String,
+    digests: HashMap<String, String>,
+}
+
+/// Checks if an index URL points to PyPI.
+fn is_pypi_index(url: &str) -> bool {
+    Url::parse(url)
+        .ok()
+        .and_then(|x| x.host_str().map(|x| x == "pypi.org"))
+        .unwrap_or(false)
+}
+
+/// Looks up the sdists of pinned packages on PyPI, keyed by normalized name.
+///
+/// The lookups run concurrently.  Packages that cannot be found (for instance
+/// because they come from a private index) are skipped with a warning.
+fn find_sdists(reqs: &[Requirement], output: CommandOutput) -> HashMap<String, (String, String)> {
+    const CONCURRENCY: usize = 8;
+    if reqs.is_empty() {
+        return HashMap::new();
+    }
+    let chunk_size = (reqs.len() + CONCURRENCY - 1) / CONCURRENCY;
+    thread::scope(|s| {
+        let handles = reqs
+            .chunks(chunk_size)
+            .map(|chunk| {
+                s.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|req| (req, find_sdist(req)))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut rv = HashMap::new();
+        for handle in handles {
+            for (req, result) in handle.join().unwrap() {
+                match result {
+                    Ok(Some(sdist)) => {
+                        rv.insert(normalize_package_name(&req.name), sdist);
+                    }
+                    Ok(None) => {}
+                    Err(err) => {
+                        if output != CommandOutput::Quiet {
+                            eprintln!(
+                                "{} skipping sdist of {}: {}",
+                                style("warning:").yellow(),
+                                req.name,
+                                err
+                            );
+                        }
+                    }
+                }
+            }
+        }
+        rv
+    })
+}
+
+/// Looks up the URL and sha256 of the sdist of a pinned package on PyPI.
+fn find_sdist(req: &Requirement) -> Result<Option<(String, String)>, Error> {
+    let version = match req.version_or_url {
+        Some(VersionOrUrl::VersionSpecifier(ref specs))
+            if specs.len() == 1 && *specs[0].operator() == Operator::Equal =>
+        {
+            specs[0].version()
+        }
+        _ => return Ok(None),
+    };
+    let url = format!("https://pypi.org/pypi/{}/{}/json", req.name, version);
+    let release: PypiRelease =
+        serde_json::from_slice(&download_url(&url, CommandOutput::Quiet)?)
+            .with_context(|| format!("invalid release info for {}=={}", req.name, version))?;
+    Ok(release
+        .urls
+        .into_iter()
+        .find(|x| x.packagetype == "sdist")
+        .and_then(|mut x| Some((x.url, x.digests.remove("sha256")?))))
+}
+
+/// Reads the pinned requirements from a lockfile.
+///
+/// Local (editable) packages and excluded packages are not returned.
+pub fn read_locked_requirements(lockfile: &Path) -> Result<Vec<Requirement>, Error> {
+    let contents = fs::read_to_string(lockfile)
+        .with_context(|| format!("failed to read lockfile {}", lockfile.display()))?;
+    let mut rv = Vec::new();
+    for line in contents.lines() {
+        let line = line.trim().trim_end_matches('\\').trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with('-') {
+            continue;
+        }
+        rv.push(
+            line.parse::<Requirement>()
+                .with_context(|| format!("invalid requirement '{}' in lockfile", line))?,
+        );
+    }
+    Ok(rv)
+}
+
+/// Computes the hash of the `pyproject.toml` files a lockfile is generated from.
+pub fn compute_pyproject_hash(pyproject: &PyProject) -> Result<String, Error> {
+    match pyproject.workspace() {
+        Some(workspace) => {
+            hash_pyproject_files(&workspace.iter_projects().collect::<Result<Vec<_>, _>>()?)
+        }
+        None => hash_pyproject_files(std::slice::from_ref(pyproject)),
+    }
+}
+
+fn hash_pyproject_files(projects: &[PyProject]) -> Result<String, Error> {
+    let mut paths = projects.iter().map(|x| x.toml_path()).collect::<Vec<_>>();
+    paths.sort();
+    let mut hasher = Sha256::new();
+    for path in paths {
+        let contents = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        // a checkout with CRLF line endings must not invalidate the lockfile
+        hasher.update(contents.replace("\r\n", "\n"));
+        hasher.update(b"\0");
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Fills in the options that were not passed with the ones the lockfile was
+/// last generated with, so that a plain `rye sync` keeps them.
+fn restore_lock_options(lockfile: &Path, lock_options: &LockOptions) -> Result<LockOptions, Error> {
+    let mut rv = lock_options.clone();
+    if rv.resolution_mode.is_none() {
+        rv.resolution_mode = Some(read_lockfile_resolution_mode(lockfile)?);
+    }
+    if rv.with_sources.is_none() {
+        rv.with_sources =
+            Some(read_lockfile_flag(lockfile, "with-sources")?.as_deref() == Some("true"));
+    }
+    if rv.annotate.is_none() {
+        rv.annotate = Some(read_lockfile_flag(lockfile, "annotate")?.as_deref() == Some("true"));
+    }
+    Ok(rv)
+}
+
+/// Reads one of the flags a lockfile was last locked with from its header.
+fn read_lockfile_flag(lockfile: &Path, name: &str) -> Result<Option<String>, Error> {
+    if !lockfile.is_file() {
+        return Ok(None);
+    }
+    let contents = fs::read_to_string(lockfile)
+        .with_context(|| format!("failed to read lockfile {}", lockfile.display()))?;
+    Ok(LOCK_FLAG_RE
+        .captures_iter(&contents)
+        .find(|m| &m[1] == name)
+        .map(|m| m[2].to_string()))
+}
+
+/// Reads the resolution mode a lockfile was generated with.
+///
+/// Missing lockfiles and lockfiles without one use the `highest` mode.
+fn read_lockfile_resolution_mode(lockfile: &Path) -> Result<ResolutionMode, Error> {
+    Ok(
+        match read_lockfile_flag(lockfile, "resolution-mode")?.as_deref() {
+            Some("lowest-direct") => ResolutionMode::LowestDirect,
+            _ => ResolutionMode::Highest,
+        },
+    )
+}
+
+/// Reads the `pyproject.toml` hash that was recorded in a lockfile.
+pub fn read_lockfile_pyproject_hash(lockfile: &Path) -> Result<Option<String>, Error> {
+    let contents = fs::read_to_string(lockfile)
+        .with_context(|| format!("failed to read lockfile {}", lockfile.display()))?;
+    Ok(PYPROJECT_HASH_RE
+        .captures(&contents)
+        .map(|m| m[1].to_string()))
+}
+
 fn make_relative_url(path: &Path, base: &Path) -> Result<String, Error> {
     // TODO: consider using ${PROJECT_ROOT} here which is what pdm does or make-req prints
     let rv = pathdiff::diff_paths(path, base).ok_or_else(|| {
@@ -419,3 +1119,166 @@ fn test_make_relativec_url() {
         "file:."
     );
 }
+
+#[test]
+fn test_read_locked_requirements() {
+    let mut lockfile = NamedTempFile::new().unwrap();
+    writeln!(
+        lockfile,
+        "# generated by rye\n\n-e file:.\ncertifi==2023.5.7\n# excluded cffi==1.15.1\nclick==8.1.3"
+    )
+    .unwrap();
+    let reqs = read_locked_requirements(lockfile.path()).unwrap();
+    assert_eq!(
+        reqs.iter().map(|x| x.name.as_str()).collect::<Vec<_>>(),
+        ["certifi", "click"]
+    );
+}
+
+#[test]
+fn test_pyproject_hash_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let toml_path = dir.path().join("pyproject.toml");
+    fs::write(&toml_path, "[project]\nname = \"foo\"\nversion = \"1.0\"\n").unwrap();
+    let pyproject = PyProject::load(&toml_path).unwrap();
+    let hash = hash_pyproject_files(std::slice::from_ref(&pyproject)).unwrap();
+
+    let generated = dir.path().join("generated.txt");
+    let lockfile = dir.path().join("requirements.lock");
+    fs::write(&generated, "click==8.1.3\n").unwrap();
+    finalize_lockfile(
+        &generated,
+        &lockfile,
+        dir.path(),
+        &HashSet::new(),
+        &LockOptions::default(),
+        &hash,
+        &HashMap::new(),
+    )
+    .unwrap();
+    assert_eq!(
+        read_lockfile_pyproject_hash(&lockfile).unwrap(),
+        Some(hash.clone())
+    );
+
+    // only changing the line endings keeps the hash
+    fs::write(
+        &toml_path,
+        "[project]\r\nname = \"foo\"\r\nversion = \"1.0\"\r\n",
+    )
+    .unwrap();
+    assert_eq!(
+        hash_pyproject_files(std::slice::from_ref(&pyproject)).unwrap(),
+        hash
+    );
+
+    // lockfiles from before the hash was recorded have none
+    fs::write(&lockfile, "# generated by rye\nclick==8.1.3\n").unwrap();
+    assert_eq!(read_lockfile_pyproject_hash(&lockfile).unwrap(), None);
+}
+
+#[test]
+fn test_restore_lock_options() {
+    let dir = tempfile::tempdir().unwrap();
+    let generated = dir.path().join("generated.txt");
+    let lockfile = dir.path().join("requirements.lock");
+
+    // without a lockfile the defaults are used
+    let restored = restore_lock_options(&lockfile, &LockOptions::default()).unwrap();
+    assert_eq!(restored.resolution_mode, Some(ResolutionMode::Highest));
+    assert_eq!(restored.with_sources, Some(false));
+
+    fs::write(&generated, "click==8.1.3\n").unwrap();
+    let lock_options = LockOptions {
+        resolution_mode: Some(ResolutionMode::LowestDirect),
+        with_sources: Some(true),
+        ..LockOptions::default()
+    };
+    finalize_lockfile(
+        &generated,
+        &lockfile,
+        dir.path(),
+        &HashSet::new(),
+        &lock_options,
+        "0",
+        &HashMap::new(),
+    )
+    .unwrap();
+    let restored = restore_lock_options(&lockfile, &LockOptions::default()).unwrap();
+    assert_eq!(restored.resolution_mode, Some(ResolutionMode::LowestDirect));
+    assert_eq!(restored.with_sources, Some(true));
+
+    // explicitly passed options win
+    let restored = restore_lock_options(
+        &lockfile,
+        &LockOptions {
+            resolution_mode: Some(ResolutionMode::Highest),
+            ..LockOptions::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(restored.resolution_mode, Some(ResolutionMode::Highest));
+}
+
+#[test]
+fn test_annotate_via_chains() {
+    let dir = tempfile::tempdir().unwrap();
+    let generated = dir.path().join("generated.txt");
+    let lockfile = dir.path().join("requirements.lock");
+    fs::write(
+        &generated,
+        "flask==2.3.2\n    # via -r /tmp/requirements.in\n\
+         jinja2==3.1.2\n    # via flask\n\
+         markupsafe==2.1.3\n    # via\n    #   jinja2\n    #   werkzeug\n\
+         werkzeug==2.3.6\n    # via flask\n",
+    )
+    .unwrap();
+    finalize_lockfile(
+        &generated,
+        &lockfile,
+        dir.path(),
+        &HashSet::new(),
+        &LockOptions {
+            annotate: Some(true),
+            ..LockOptions::default()
+        },
+        "0",
+        &HashMap::new(),
+    )
+    .unwrap();
+    let contents = fs::read_to_string(&lockfile).unwrap();
+    let body = contents.split_once("# pyproject-hash: 0\n").unwrap().1;
+    assert_eq!(
+        body.trim(),
+        "flask==2.3.2\n    # via -r pyproject.toml\n\
+         jinja2==3.1.2\n    # via flask\n\
+         markupsafe==2.1.3\n    # via\n    #   flask -> jinja2\n    #   flask -> werkzeug\n\
+         werkzeug==2.3.6\n    # via flask"
+    );
+}
+
+#[test]
+fn test_extra_dependencies() {
+    let dir = tempfile::tempdir().unwrap();
+    let toml_path = dir.path().join("pyproject.toml");
+    fs::write(
+        &toml_path,
+        "[project]\nname = \"My_Project\"\ndependencies = [\"flask~=2.0\", \"click\"]\n",
+    )
+    .unwrap();
+    let pyproject = PyProject::load(&toml_path).unwrap();
+    let lock_options = LockOptions {
+        extra_dependencies: vec![ExtraDependency {
+            project: "my-project".into(),
+            kind: DependencyKind::Normal,
+            dep: DependencyRef::new("Flask>=3.0"),
+        }],
+        ..LockOptions::default()
+    };
+    let deps = get_dependencies(&pyproject, DependencyKind::Normal, &lock_options)
+        .iter()
+        .map(|x| x.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(deps, vec!["click", "Flask>=3.0"]);
+    assert!(get_dependencies(&pyproject, DependencyKind::Dev, &lock_options).is_empty());
+}
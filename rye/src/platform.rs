@@ -1,10 +1,16 @@
 use std::env::consts::{ARCH, OS};
+use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
-use std::sync::Mutex;
+use std::sync::{Mutex, Once};
 use std::{env, fs};
 
-use anyhow::{anyhow, Context, Error};
+use age::secrecy::{ExposeSecret, Secret};
+use age::{Decryptor, Encryptor};
+use anyhow::{anyhow, bail, Context, Error};
+use console::style;
+use tempfile::NamedTempFile;
+use url::Url;
 
 use crate::sources::{get_download_url, PythonVersion, PythonVersionRequest};
 
@@ -166,8 +172,29 @@ pub fn get_default_author() -> Option<(String, String)> {
     Some((name?, email.unwrap_or_else(|| "".into())))
 }
 
+/// Returns the toolchain forced with the `RYE_FORCE_TOOLCHAIN` environment variable.
+pub fn get_forced_toolchain() -> Option<PythonVersion> {
+    let value = env::var("RYE_FORCE_TOOLCHAIN").ok()?;
+    match value.trim().parse() {
+        Ok(ver) => Some(ver),
+        Err(_) => {
+            static WARNED: Once = Once::new();
+            WARNED.call_once(|| {
+                eprintln!("warning: ignoring invalid RYE_FORCE_TOOLCHAIN '{}'", value);
+            });
+            None
+        }
+    }
+}
+
 /// Reads the current `.python-version` file.
+///
+/// If no `.python-version` file is found, the global pin is used.  A toolchain
+/// forced with `RYE_FORCE_TOOLCHAIN` takes precedence over both.
 pub fn get_python_version_from_pyenv_pin() -> Option<PythonVersion> {
+    if let Some(ver) = get_forced_toolchain() {
+        return Some(ver);
+    }
     let mut here = env::current_dir().ok()?;
 
     loop {
@@ -182,7 +209,17 @@ pub fn get_python_version_from_pyenv_pin() -> Option<PythonVersion> {
         }
     }
 
-    None
+    // fall back to the global pin.
+    fs::read_to_string(get_global_python_version_filepath())
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Returns the path of the global `python-version` pin.
+pub fn get_global_python_version_filepath() -> PathBuf {
+    get_app_dir().join("python-version")
 }
 
 /// Returns the most recent cpython release.
@@ -202,7 +239,7 @@ pub fn get_latest_cpython() -> Result<PythonVersion, Error> {
     .context("unsupported platform")
 }
 
-/// Returns the credentials data from ~/.rye.
+/// Returns the credentials data.
 ///
 /// The credentials file contains toml tables for various credential data.
 /// ```toml
@@ -210,10 +247,13 @@ pub fn get_latest_cpython() -> Result<PythonVersion, Error> {
 /// token = ""
 /// ```
 pub fn get_credentials() -> Result<toml_edit::Document, Error> {
-    let filepath = get_credentials_filepath()?;
+    let filepath = credentials_path();
 
     // If a credentials file doesn't exist create an empty one. TODO: Move to bootstrapping?
     if !filepath.exists() {
+        if let Some(parent) = filepath.parent() {
+            fs::create_dir_all(parent)?;
+        }
         fs::write(&filepath, "")?;
     }
 
@@ -225,10 +265,174 @@ pub fn get_credentials() -> Result<toml_edit::Document, Error> {
 }
 
 pub fn write_credentials(doc: &toml_edit::Document) -> Result<(), Error> {
-    std::fs::write(get_credentials_filepath()?, doc.to_string())
-        .context("unable to write to the credentials file")
+    let filepath = credentials_path();
+    if let Some(parent) = filepath.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    std::fs::write(filepath, doc.to_string()).context("unable to write to the credentials file")
+}
+
+/// Encrypts a token with a passphrase.
+pub fn encrypt(secret: &Secret<String>, phrase: Secret<String>) -> Result<Vec<u8>, Error> {
+    let encryptor = Encryptor::with_user_passphrase(phrase);
+    let mut encrypted = vec![];
+    let mut writer = encryptor.wrap_output(&mut encrypted)?;
+    writer.write_all(secret.expose_secret().as_bytes())?;
+    writer.finish()?;
+
+    Ok(encrypted)
+}
+
+/// Decrypts a hex encoded token that was encrypted with a passphrase.
+pub fn decrypt(secret: &Secret<String>, phrase: &Secret<String>) -> Result<Secret<String>, Error> {
+    // If a passphrase is provided we assume the secret is encoded bytes from encryption.
+    let bytes = hex::decode(pad_hex(secret.expose_secret().clone()))?;
+    if let Decryptor::Passphrase(decryptor) = Decryptor::new(bytes.as_slice())? {
+        // Do the decryption
+        let mut decrypted = vec![];
+        let mut reader = decryptor.decrypt(phrase, None)?;
+        reader.read_to_end(&mut decrypted)?;
+
+        let token = String::from_utf8(decrypted).context("failed to parse utf-8")?;
+        let secret = Secret::new(token);
+
+        return Ok(secret);
+    }
+
+    bail!("failed to decrypt")
+}
+
+/// Strips the quotes and backslashes of a token read from the credentials file.
+pub fn escape_string(s: String) -> String {
+    s.trim().replace(['\\', '"'], "")
+}
+
+fn pad_hex(s: String) -> String {
+    if s.len() % 2 == 1 {
+        format!("0{}", s)
+    } else {
+        s
+    }
+}
+
+/// Writes a netrc file with the credentials of the given package indexes.
+///
+/// The credentials are looked up in the credentials file by the host name of
+/// the index, with a `token` and an optional `username` (`__token__` if not
+/// set).  Tokens encrypted with a passphrase are decrypted after prompting for
+/// it, or skipped if there is nobody to ask.  The entries of the user's own
+/// netrc file are kept.  Returns `None` if none of the indexes has credentials.
+pub fn get_index_netrc(index_urls: &[String]) -> Result<Option<NamedTempFile>, Error> {
+    if index_urls.is_empty() || !credentials_path().is_file() {
+        return Ok(None);
+    }
+    let credentials = get_credentials()?;
+    let mut entries = Vec::new();
+    for url in index_urls {
+        let host = match Url::parse(url)
+            .ok()
+            .and_then(|x| x.host_str().map(String::from))
+        {
+            Some(host) => host,
+            None => continue,
+        };
+        let table = credentials.get(&host);
+        if let Some(token) = table.and_then(|x| x.get("token")).and_then(|x| x.as_str()) {
+            let token = if is_encrypted_token(token) {
+                match prompt_decrypt_token(&host, token)? {
+                    Some(token) => token,
+                    None => continue,
+                }
+            } else {
+                token.to_string()
+            };
+            let username = table
+                .and_then(|x| x.get("username"))
+                .and_then(|x| x.as_str())
+                .unwrap_or("__token__");
+            entries.push(format!(
+                "machine {} login {} password {}",
+                host, username, token
+            ));
+        }
+    }
+    if entries.is_empty() {
+        return Ok(None);
+    }
+
+    let mut rv = NamedTempFile::new()?;
+    let user_netrc = env::var_os("NETRC")
+        .map(PathBuf::from)
+        .or_else(|| simple_home_dir::home_dir().map(|x| x.join(".netrc")));
+    if let Some(contents) = user_netrc.and_then(|x| fs::read_to_string(x).ok()) {
+        writeln!(rv, "{}", contents)?;
+    }
+    for entry in entries {
+        writeln!(rv, "{}", entry)?;
+    }
+    rv.flush()?;
+    Ok(Some(rv))
+}
+
+/// Checks if a stored token is the hex encoded output of `age`.
+pub fn is_encrypted_token(token: &str) -> bool {
+    hex::decode(pad_hex(token.to_string()))
+        .map(|x| x.starts_with(b"age-encryption.org/"))
+        .unwrap_or(false)
+}
+
+fn prompt_decrypt_token(host: &str, token: &str) -> Result<Option<String>, Error> {
+    if !console::user_attended_stderr() {
+        eprintln!(
+            "{} the token for {} is encrypted, not using it",
+            style("warning:").yellow(),
+            host
+        );
+        return Ok(None);
+    }
+    let phrase = dialoguer::Password::new()
+        .with_prompt(format!("Enter the passphrase of the token for {}", host))
+        .report(false)
+        .interact()
+        .map(Secret::new)?;
+    let token = decrypt(&Secret::new(token.to_string()), &phrase)
+        .with_context(|| format!("failed to decrypt token for {}", host))?;
+    Ok(Some(token.expose_secret().clone()))
+}
+
+/// Returns the path of the credentials file.
+///
+/// On Linux this is `$XDG_CONFIG_HOME/rye/credentials` unless only the
+/// legacy `~/.rye/credentials` exists or `RYE_HOME` is set.  Everywhere else
+/// the file is placed in the rye home folder.
+pub fn credentials_path() -> PathBuf {
+    let legacy = get_app_dir().join("credentials");
+    #[cfg(target_os = "linux")]
+    {
+        if env::var_os("RYE_HOME").is_none() {
+            let config_dir = env::var_os("XDG_CONFIG_HOME")
+                .map(PathBuf::from)
+                .filter(|x| x.is_absolute())
+                .or_else(|| simple_home_dir::home_dir().map(|x| x.join(".config")));
+            if let Some(config_dir) = config_dir {
+                let path = config_dir.join("rye").join("credentials");
+                if path.is_file() || !legacy.is_file() {
+                    return path;
+                }
+            }
+        }
+    }
+    legacy
 }
 
-pub fn get_credentials_filepath() -> Result<PathBuf, Error> {
-    Ok(get_app_dir().join("credentials"))
+#[test]
+fn test_encrypted_token_round_trip() {
+    let secret = Secret::new("pypi-secret".to_string());
+    let phrase = Secret::new("phrase".to_string());
+    let encrypted = hex::encode(encrypt(&secret, phrase.clone()).unwrap());
+    assert!(is_encrypted_token(&encrypted));
+    assert!(!is_encrypted_token("pypi-secret"));
+    assert!(!is_encrypted_token("deadbeef"));
+    let decrypted = decrypt(&Secret::new(encrypted), &phrase).unwrap();
+    assert_eq!(decrypted.expose_secret(), "pypi-secret");
 }
@@ -32,6 +32,7 @@ pub enum DependencyKind<'a> {
     Dev,
     Excluded,
     Optional(Cow<'a, str>),
+    Group(Cow<'a, str>),
 }
 
 impl<'a> fmt::Display for DependencyKind<'a> {
@@ -41,6 +42,7 @@ impl<'a> fmt::Display for DependencyKind<'a> {
             DependencyKind::Dev => f.write_str("dev"),
             DependencyKind::Excluded => f.write_str("excluded"),
             DependencyKind::Optional(ref sect) => write!(f, "optional ({})", sect),
+            DependencyKind::Group(ref group) => write!(f, "group ({})", group),
         }
     }
 }
@@ -495,6 +497,14 @@ impl PyProject {
             .and_then(|x| x.as_str())
     }
 
+    /// Returns the project version.
+    pub fn version(&self) -> Option<&str> {
+        self.doc
+            .get("project")
+            .and_then(|x| x.get("version"))
+            .and_then(|x| x.as_str())
+    }
+
     /// Returns the normalized name.
     pub fn normalized_name(&self) -> Result<String, Error> {
         self.name()
@@ -543,6 +553,161 @@ impl PyProject {
         rv
     }
 
+    /// Returns the script that should run before building the project.
+    pub fn pre_build_script(&self) -> Option<PathBuf> {
+        self.doc
+            .get("tool")
+            .and_then(|x| x.get("rye"))
+            .and_then(|x| x.get("build"))
+            .and_then(|x| x.get("pre-script"))
+            .and_then(|x| x.as_str())
+            .map(|x| self.root_path().join(x))
+    }
+
+    /// Checks if `rye build` requires a clean git working tree.
+    pub fn require_clean_git(&self) -> bool {
+        self.doc
+            .get("tool")
+            .and_then(|x| x.get("rye"))
+            .and_then(|x| x.get("build"))
+            .and_then(|x| x.get("require-clean-git"))
+            .and_then(|x| x.as_bool())
+            .unwrap_or(false)
+    }
+
+    /// Returns the webhook URL that is notified after publishing.
+    pub fn publish_announce_url(&self) -> Option<&str> {
+        self.doc
+            .get("tool")
+            .and_then(|x| x.get("rye"))
+            .and_then(|x| x.get("publish"))
+            .and_then(|x| x.get("announce-url"))
+            .and_then(|x| x.as_str())
+    }
+
+    /// Returns the `[project]` table.
+    pub fn project_metadata(&self) -> Option<&Item> {
+        self.doc.get("project")
+    }
+
+    /// Returns the normalized names of dependencies that are installed as editables.
+    pub fn editable_dependencies(&self) -> HashSet<String> {
+        self.doc
+            .get("tool")
+            .and_then(|x| x.get("rye"))
+            .and_then(|x| x.get("editable-dependencies"))
+            .and_then(|x| x.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|x| x.as_str())
+            .map(normalize_package_name)
+            .collect()
+    }
+
+    /// Returns the packages that are left out of the lockfiles.
+    pub fn lock_exclude(&self) -> Vec<String> {
+        self.doc
+            .get("tool")
+            .and_then(|x| x.get("rye"))
+            .and_then(|x| x.get("lock"))
+            .and_then(|x| x.get("exclude"))
+            .and_then(|x| x.as_array())
+            .map(toml_array_as_string_array)
+            .unwrap_or_default()
+    }
+
+    /// Marks a dependency to be installed as editable.
+    pub fn add_editable_dependency(&mut self, name: &str) -> Result<(), Error> {
+        let tool = table_entry(self.doc.as_table_mut(), "tool", true)?;
+        let rye = table_entry(tool, "rye", true)?;
+        let editables = &mut rye["editable-dependencies"];
+        if editables.is_none() {
+            *editables = Item::Value(Value::Array(Array::new()));
+        }
+        let editables = editables
+            .as_array_mut()
+            .ok_or_else(|| anyhow!("editable dependencies in pyproject.toml are malformed"))?;
+        let name = normalize_package_name(name);
+        if !editables
+            .iter()
+            .any(|x| x.as_str().map(normalize_package_name).as_ref() == Some(&name))
+        {
+            editables.push(name);
+        }
+        Ok(())
+    }
+
+    /// Returns the extra package indexes from `tool.rye.sources`.
+    pub fn sources(&self) -> Vec<String> {
+        self.doc
+            .get("tool")
+            .and_then(|x| x.get("rye"))
+            .and_then(|x| x.get("sources"))
+            .and_then(|x| x.as_table_like())
+            .into_iter()
+            .flat_map(|x| x.iter())
+            .filter_map(|x| x.1.as_str())
+            .map(|x| x.to_string())
+            .collect()
+    }
+
+    /// Records a package index in `tool.rye.sources` under the given name.
+    pub fn add_source(&mut self, name: &str, url: &str) -> Result<(), Error> {
+        let tool = table_entry(self.doc.as_table_mut(), "tool", true)?;
+        let rye = table_entry(tool, "rye", true)?;
+        let sources = table_entry(rye, "sources", false)?;
+        if !sources.iter().any(|x| x.1.as_str() == Some(url)) {
+            sources[name] = toml_edit::value(url);
+        }
+        Ok(())
+    }
+
+    /// Returns the path of the lockfile.
+    ///
+    /// This is `tool.rye.lock-file` relative to the workspace root, or
+    /// `requirements.lock` if not configured.  The dev lockfile has `-dev`
+    /// appended to the file stem (`requirements-dev.lock`).
+    pub fn lockfile_path(&self, dev: bool) -> PathBuf {
+        let lockfile = self
+            .workspace_path()
+            .join(self.lock_file().unwrap_or("requirements.lock"));
+        if !dev {
+            return lockfile;
+        }
+        let mut filename = lockfile.file_stem().unwrap_or_default().to_os_string();
+        filename.push("-dev");
+        if let Some(ext) = lockfile.extension() {
+            filename.push(".");
+            filename.push(ext);
+        }
+        lockfile.with_file_name(filename)
+    }
+
+    /// Returns the configured lockfile (`tool.rye.lock-file`).
+    ///
+    /// In a workspace this is read from the workspace root.
+    pub fn lock_file(&self) -> Option<&str> {
+        self.workspace()
+            .map_or(&self.doc, |x| &x.doc)
+            .get("tool")
+            .and_then(|x| x.get("rye"))
+            .and_then(|x| x.get("lock-file"))
+            .and_then(|x| x.as_str())
+    }
+
+    /// Sets the lockfile path, relative to the workspace root.
+    ///
+    /// This can only be set on the workspace root.
+    pub fn set_lock_file(&mut self, path: &str) -> Result<(), Error> {
+        if !self.is_workspace_root() {
+            bail!("the lockfile can only be configured in the workspace root");
+        }
+        let tool = table_entry(self.doc.as_table_mut(), "tool", true)?;
+        let rye = table_entry(tool, "rye", true)?;
+        rye["lock-file"] = toml_edit::value(path);
+        Ok(())
+    }
+
     /// Returns a set of all extras.
     pub fn extras(&self) -> HashSet<&str> {
         self.doc
@@ -556,6 +721,18 @@ impl PyProject {
             .collect()
     }
 
+    /// Returns a set of all dependency groups.
+    pub fn dependency_groups(&self) -> HashSet<&str> {
+        self.doc
+            .get("dependency-groups")
+            .and_then(|x| x.as_table_like())
+            .map_or(None.into_iter(), |x| {
+                Some(x.iter().map(|x| x.0)).into_iter()
+            })
+            .flatten()
+            .collect()
+    }
+
     /// Adds a dependency.
     pub fn add_dependency(
         &mut self,
@@ -563,6 +740,9 @@ impl PyProject {
         kind: &DependencyKind,
     ) -> Result<(), Error> {
         let dependencies = match kind {
+            DependencyKind::Group(ref group) => {
+                return self.add_dependency_group_member(group, req);
+            }
             DependencyKind::Normal => &mut self.doc["project"]["dependencies"],
             DependencyKind::Dev => &mut self.doc["tool"]["rye"]["dev-dependencies"],
             DependencyKind::Excluded => &mut self.doc["tool"]["rye"]["excluded-dependencies"],
@@ -587,6 +767,27 @@ impl PyProject {
         Ok(())
     }
 
+    /// Adds a dependency to a [PEP 735](https://peps.python.org/pep-0735/)
+    /// dependency group, creating the group if necessary.
+    pub fn add_dependency_group_member(
+        &mut self,
+        group: &str,
+        req: &Requirement,
+    ) -> Result<(), Error> {
+        let groups = table_entry(self.doc.as_table_mut(), "dependency-groups", false)?;
+        let dependencies = &mut groups[group];
+        if dependencies.is_none() {
+            *dependencies = Item::Value(Value::Array(Array::new()));
+        }
+        set_dependency(
+            dependencies
+                .as_array_mut()
+                .ok_or_else(|| anyhow!("dependency group '{}' is malformed", group))?,
+            req,
+        );
+        Ok(())
+    }
+
     /// Removes a dependency
     pub fn remove_dependency(
         &mut self,
@@ -600,6 +801,7 @@ impl PyProject {
             DependencyKind::Optional(ref section) => {
                 &mut self.doc["project"]["optional-dependencies"][section as &str]
             }
+            DependencyKind::Group(ref group) => &mut self.doc["dependency-groups"][group as &str],
         };
         if !dependencies.is_none() {
             Ok(remove_dependency(
@@ -613,6 +815,52 @@ impl PyProject {
         }
     }
 
+    /// Adds a member to the workspace declared by this project.
+    ///
+    /// Returns `false` if the member was already declared.
+    pub fn add_workspace_member(&mut self, member: &str) -> Result<bool, Error> {
+        let tool = table_entry(self.doc.as_table_mut(), "tool", true)?;
+        let rye = table_entry(tool, "rye", true)?;
+        let members = &mut table_entry(rye, "workspace", false)?["members"];
+        if members.is_none() {
+            *members = Item::Value(Value::Array(Array::new()));
+        }
+        let members = members
+            .as_array_mut()
+            .ok_or_else(|| anyhow!("workspace members in pyproject.toml are malformed"))?;
+        if members.iter().any(|x| x.as_str() == Some(member)) {
+            return Ok(false);
+        }
+        members.push(member);
+        Ok(true)
+    }
+
+    /// Removes a member from the workspace declared by this project.
+    ///
+    /// Returns `false` if the member was not declared.
+    pub fn remove_workspace_member(&mut self, member: &str) -> Result<bool, Error> {
+        let members = match self
+            .doc
+            .get_mut("tool")
+            .and_then(|x| x.get_mut("rye"))
+            .and_then(|x| x.get_mut("workspace"))
+            .and_then(|x| x.get_mut("members"))
+        {
+            Some(members) => members
+                .as_array_mut()
+                .ok_or_else(|| anyhow!("workspace members in pyproject.toml are malformed"))?,
+            None => return Ok(false),
+        };
+        let idx = members.iter().position(|x| x.as_str() == Some(member));
+        match idx {
+            Some(idx) => {
+                members.remove(idx);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
     /// Iterates over all dependencies.
     pub fn iter_dependencies(
         &self,
@@ -635,6 +883,10 @@ impl PyProject {
                 .get("project")
                 .and_then(|x| x.get("optional-dependencies"))
                 .and_then(|x| x.get(section as &str)),
+            DependencyKind::Group(ref group) => self
+                .doc
+                .get("dependency-groups")
+                .and_then(|x| x.get(group as &str)),
         };
         sec.and_then(|x| x.as_array())
             .into_iter()
@@ -664,6 +916,23 @@ pub fn normalize_package_name(x: &str) -> String {
         })
 }
 
+/// Returns a sub table, creating it if it's missing.
+fn table_entry<'a>(
+    table: &'a mut Table,
+    key: &str,
+    implicit: bool,
+) -> Result<&'a mut Table, Error> {
+    table
+        .entry(key)
+        .or_insert_with(|| {
+            let mut rv = Table::new();
+            rv.set_implicit(implicit);
+            Item::Table(rv)
+        })
+        .as_table_mut()
+        .ok_or_else(|| anyhow!("'{}' in pyproject.toml is malformed", key))
+}
+
 fn set_dependency(deps: &mut Array, req: &Requirement) {
     let mut to_replace = None;
     for (idx, dep) in deps.iter().enumerate() {
@@ -183,7 +183,8 @@ impl fmt::Display for PythonVersionRequest {
     }
 }
 
-fn matches_version(req: &PythonVersionRequest, v: &PythonVersion) -> bool {
+/// Checks if a version satisfies a version request.
+pub fn matches_version(req: &PythonVersionRequest, v: &PythonVersion) -> bool {
     if req.kind.as_deref().unwrap_or(DEFAULT_KIND) != v.kind {
         return false;
     }
@@ -1,22 +1,29 @@
-use std::path::Path;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::str::FromStr;
 use std::{env, fs};
 
 use anyhow::{bail, Context, Error};
 use console::style;
+use pep440_rs::Version;
 use serde::{Deserialize, Serialize};
 use tempfile::tempdir;
 
 use crate::bootstrap::{ensure_self_venv, fetch, get_pip_module};
 use crate::consts::VENV_BIN;
 use crate::lock::{
-    update_single_project_lockfile, update_workspace_lockfile, LockMode, LockOptions,
+    compute_pyproject_hash, read_locked_requirements, read_lockfile_indexes,
+    read_lockfile_pyproject_hash, update_single_project_lockfile, update_workspace_lockfile,
+    LockMode, LockOptions,
 };
 use crate::piptools::get_pip_sync;
-use crate::platform::get_toolchain_python_bin;
-use crate::pyproject::{get_current_venv_python_version, PyProject};
+use crate::platform::{get_forced_toolchain, get_index_netrc, get_toolchain_python_bin};
+use crate::pyproject::{get_current_venv_python_version, normalize_package_name, PyProject};
 use crate::sources::PythonVersion;
-use crate::utils::{get_venv_python_bin, symlink_dir, CommandOutput};
+use crate::utils::{
+    format_requirement, get_venv_python_bin, symlink_dir, CommandOutput, QuietExit,
+};
 
 /// Controls the sync mode
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
@@ -43,8 +50,16 @@ pub struct SyncOptions {
     pub mode: SyncMode,
     /// Forces venv creation even when unsafe.
     pub force: bool,
-    /// Do not lock.
+    /// Resolve without reading or writing the lockfiles.
     pub no_lock: bool,
+    /// Do not lock and fail if the lockfile is outdated.
+    pub frozen: bool,
+    /// Print the changes to the lockfiles.
+    pub show_diff: bool,
+    /// Only print the changes to the lockfiles, without writing them or installing.
+    pub dry_run: bool,
+    /// With `dry_run`, exit with status 1 if the lockfiles would change.
+    pub check: bool,
     /// Controls locking.
     pub lock_options: LockOptions,
 }
@@ -63,22 +78,63 @@ impl SyncOptions {
 #[derive(Serialize, Deserialize, Debug)]
 pub struct VenvMarker {
     pub python: PythonVersion,
+    /// Extra packages injected into a tool's virtualenv.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub injected: Vec<String>,
 }
 
 /// Synchronizes a project's virtualenv.
 pub fn sync(cmd: SyncOptions) -> Result<(), Error> {
-    let pyproject = PyProject::discover()?;
-    let lockfile = pyproject.workspace_path().join("requirements.lock");
-    let dev_lockfile = pyproject.workspace_path().join("requirements-dev.lock");
+    sync_project(PyProject::discover()?, cmd)
+}
+
+fn sync_project(pyproject: PyProject, mut cmd: SyncOptions) -> Result<(), Error> {
+    // without locking, resolve into throwaway lockfiles instead.  A dry run
+    // starts out from copies of the real lockfiles.
+    let real_lockfile = pyproject.lockfile_path(false);
+    let real_dev_lockfile = pyproject.lockfile_path(true);
+    let scratch_lockfiles = if cmd.no_lock || cmd.dry_run {
+        Some(tempdir()?)
+    } else {
+        None
+    };
+    let (lockfile, dev_lockfile) = match scratch_lockfiles {
+        Some(ref dir) => (
+            dir.path()
+                .join(real_lockfile.file_name().unwrap_or_default()),
+            dir.path()
+                .join(real_dev_lockfile.file_name().unwrap_or_default()),
+        ),
+        None => (real_lockfile.clone(), real_dev_lockfile.clone()),
+    };
+    if cmd.dry_run {
+        for (src, dst) in [
+            (&real_lockfile, &lockfile),
+            (&real_dev_lockfile, &dev_lockfile),
+        ] {
+            if src.is_file() {
+                fs::copy(src, dst)?;
+            }
+        }
+    }
     let venv = pyproject.venv_path();
     let py_ver = pyproject.venv_python_version()?;
     let output = cmd.output;
+    if output == CommandOutput::Verbose && get_forced_toolchain().is_some() {
+        eprintln!("Using toolchain {} from RYE_FORCE_TOOLCHAIN", py_ver);
+    }
+
+    // a dry run never fetches a toolchain or touches the virtualenv, it
+    // resolves with whatever interpreter is already there.
+    if cmd.dry_run {
+        cmd.lock_options.python = Some(find_dry_run_python(&venv, &py_ver)?);
+    }
 
     // ensure we are bootstrapped
     let self_venv = ensure_self_venv(output).context("could not sync because bootstrap failed")?;
 
-    let mut recreate = cmd.mode == SyncMode::Full;
-    if venv.is_dir() {
+    let mut recreate = cmd.mode == SyncMode::Full && !cmd.dry_run;
+    if venv.is_dir() && !cmd.dry_run {
         if let Some(marker_python) = get_current_venv_python_version(&venv) {
             if marker_python != py_ver {
                 if cmd.output != CommandOutput::Quiet {
@@ -103,17 +159,21 @@ pub fn sync(cmd: SyncOptions) -> Result<(), Error> {
     }
 
     // make sure we have a compatible python version
-    let py_ver =
-        fetch(&py_ver.into(), output).context("failed fetching toolchain ahead of sync")?;
+    let py_ver = if cmd.dry_run {
+        py_ver
+    } else {
+        fetch(&py_ver.into(), output).context("failed fetching toolchain ahead of sync")?
+    };
 
     // kill the virtualenv if it's there and we need to get rid of it.
     if recreate {
         fs::remove_dir_all(&venv).ok();
     }
 
-    if venv.is_dir() {
+    if venv.is_dir() || cmd.dry_run {
         // we only care about this output if regular syncs are used
         if !matches!(cmd.mode, SyncMode::PythonOnly | SyncMode::LockOnly)
+            && !cmd.dry_run
             && output != CommandOutput::Quiet
         {
             eprintln!("Reusing already existing virtualenv");
@@ -132,6 +192,7 @@ pub fn sync(cmd: SyncOptions) -> Result<(), Error> {
             venv.join("rye-venv.json"),
             serde_json::to_string_pretty(&VenvMarker {
                 python: py_ver.clone(),
+                injected: Vec::new(),
             })?,
         )
         .context("failed writing venv marker file")?;
@@ -142,7 +203,26 @@ pub fn sync(cmd: SyncOptions) -> Result<(), Error> {
     // into a folder all by itself and place a second file in there which we
     // can pass to pip-sync to install the local package.
     if recreate || cmd.mode != SyncMode::PythonOnly {
-        if cmd.no_lock {
+        if cmd.no_lock && output != CommandOutput::Quiet {
+            eprintln!(
+                "{} resolving without lockfiles, the environment is not reproducible",
+                style("warning:").yellow()
+            );
+        }
+
+        let old_lockfiles = if (cmd.show_diff || cmd.dry_run || cmd.lock_options.update_all)
+            && !cmd.no_lock
+            && !cmd.frozen
+        {
+            Some((
+                read_pinned_versions(&real_lockfile)?,
+                read_pinned_versions(&real_dev_lockfile)?,
+            ))
+        } else {
+            None
+        };
+
+        if cmd.frozen {
             let lockfile = if cmd.dev { &dev_lockfile } else { &lockfile };
             if !lockfile.is_file() {
                 bail!(
@@ -150,6 +230,23 @@ pub fn sync(cmd: SyncOptions) -> Result<(), Error> {
                     lockfile.display()
                 );
             }
+            match read_lockfile_pyproject_hash(lockfile)? {
+                // lockfiles from older rye versions cannot be checked
+                None => {
+                    if output != CommandOutput::Quiet {
+                        eprintln!(
+                            "{} lockfile '{}' has no pyproject hash, re-run `rye lock`",
+                            style("warning:").yellow(),
+                            lockfile.display()
+                        );
+                    }
+                }
+                Some(hash) if hash != compute_pyproject_hash(&pyproject)? => bail!(
+                    "lockfile '{}' is out of date with pyproject.toml. Run `rye lock` to update it.",
+                    lockfile.display()
+                ),
+                Some(_) => {}
+            }
         } else if let Some(workspace) = pyproject.workspace() {
             // make sure we have an up-to-date lockfile
             update_workspace_lockfile(
@@ -192,8 +289,22 @@ pub fn sync(cmd: SyncOptions) -> Result<(), Error> {
             .context("could not write dev lockfile for project")?;
         }
 
+        if let Some((old, old_dev)) = old_lockfiles {
+            if cmd.show_diff || cmd.dry_run {
+                let changed = print_lockfile_diff(&lockfile, &old)?;
+                let dev_changed = print_lockfile_diff(&dev_lockfile, &old_dev)?;
+                if cmd.dry_run && cmd.check && (changed || dev_changed) {
+                    return Err(QuietExit(1).into());
+                }
+            }
+            if cmd.lock_options.update_all && output != CommandOutput::Quiet {
+                print_lockfile_summary(&lockfile, &old)?;
+                print_lockfile_summary(&dev_lockfile, &old_dev)?;
+            }
+        }
+
         // run pip install with the lockfile.
-        if cmd.mode != SyncMode::LockOnly {
+        if cmd.mode != SyncMode::LockOnly && !cmd.dry_run {
             if output != CommandOutput::Quiet {
                 eprintln!("Installing dependencies");
             }
@@ -219,10 +330,23 @@ pub fn sync(cmd: SyncOptions) -> Result<(), Error> {
                 // spaces in paths
                 .arg(format!("--python=\"{}\" --no-deps", py_path.display()));
 
-            if cmd.dev && dev_lockfile.is_file() {
-                pip_sync_cmd.arg(&dev_lockfile);
+            let sync_lockfile = if cmd.dev && dev_lockfile.is_file() {
+                &dev_lockfile
             } else {
-                pip_sync_cmd.arg(&lockfile);
+                &lockfile
+            };
+            pip_sync_cmd.arg(sync_lockfile);
+
+            // private indexes get their credentials from the credentials file
+            let (index_url, extra_index_urls) = read_lockfile_indexes(sync_lockfile)?;
+            let netrc = get_index_netrc(
+                &index_url
+                    .into_iter()
+                    .chain(extra_index_urls)
+                    .collect::<Vec<_>>(),
+            )?;
+            if let Some(ref netrc) = netrc {
+                pip_sync_cmd.env("NETRC", netrc.path());
             }
 
             if output == CommandOutput::Verbose {
@@ -249,6 +373,108 @@ pub fn sync(cmd: SyncOptions) -> Result<(), Error> {
     Ok(())
 }
 
+/// Reads the pinned versions from a lockfile keyed by normalized name.
+fn read_pinned_versions(lockfile: &Path) -> Result<BTreeMap<String, String>, Error> {
+    if !lockfile.is_file() {
+        return Ok(BTreeMap::new());
+    }
+    Ok(read_locked_requirements(lockfile)?
+        .into_iter()
+        .map(|req| {
+            let name = normalize_package_name(&req.name);
+            let version = format_requirement(&req)
+                .to_string()
+                .trim_start_matches(&req.name)
+                .to_string();
+            (name, version)
+        })
+        .collect())
+}
+
+/// Picks the interpreter a dry run resolves with.
+///
+/// This is the virtualenv's if it has the right version, otherwise the
+/// toolchain if it is already installed.
+fn find_dry_run_python(venv: &Path, py_ver: &PythonVersion) -> Result<PathBuf, Error> {
+    if get_current_venv_python_version(venv).as_ref() == Some(py_ver) {
+        return Ok(get_venv_python_bin(venv));
+    }
+    match get_toolchain_python_bin(py_ver) {
+        Ok(python) if python.is_file() => Ok(python),
+        _ => bail!(
+            "Python {} is not installed and a dry run does not fetch it. Run `rye fetch {}` first.",
+            py_ver,
+            py_ver
+        ),
+    }
+}
+
+/// Prints the packages that were added, removed or changed in a lockfile.
+///
+/// Returns `true` if anything changed.
+fn print_lockfile_diff(lockfile: &Path, old: &BTreeMap<String, String>) -> Result<bool, Error> {
+    let new = read_pinned_versions(lockfile)?;
+    let mut changes = Vec::new();
+    for (name, version) in old {
+        match new.get(name) {
+            None => changes.push(format!("{} {}{}", style("-").red(), name, version)),
+            Some(new_version) if new_version != version => changes.push(format!(
+                "{} {} {} -> {}",
+                style("~").yellow(),
+                name,
+                version.trim_start_matches("=="),
+                new_version.trim_start_matches("==")
+            )),
+            Some(_) => {}
+        }
+    }
+    for (name, version) in &new {
+        if !old.contains_key(name) {
+            changes.push(format!("{} {}{}", style("+").green(), name, version));
+        }
+    }
+
+    let filename = lockfile.file_name().unwrap_or_default().to_string_lossy();
+    if changes.is_empty() {
+        eprintln!("No changes to {}", filename);
+    } else {
+        eprintln!("Changes to {}:", filename);
+        for change in &changes {
+            eprintln!("  {}", change);
+        }
+    }
+    Ok(!changes.is_empty())
+}
+
+/// Prints how many packages were upgraded, downgraded, added or removed in a lockfile.
+fn print_lockfile_summary(lockfile: &Path, old: &BTreeMap<String, String>) -> Result<(), Error> {
+    let parse = |x: &str| Version::from_str(x.trim_start_matches("==")).ok();
+    let new = read_pinned_versions(lockfile)?;
+    let (mut upgraded, mut downgraded, mut removed) = (0, 0, 0);
+    for (name, version) in old {
+        match new.get(name) {
+            None => removed += 1,
+            Some(new_version) if new_version != version => {
+                match (parse(version), parse(new_version)) {
+                    (Some(old), Some(new)) if new < old => downgraded += 1,
+                    _ => upgraded += 1,
+                }
+            }
+            Some(_) => {}
+        }
+    }
+    let added = new.keys().filter(|x| !old.contains_key(*x)).count();
+    eprintln!(
+        "{}: {} upgraded, {} downgraded, {} added, {} removed",
+        lockfile.file_name().unwrap_or_default().to_string_lossy(),
+        upgraded,
+        downgraded,
+        added,
+        removed
+    );
+    Ok(())
+}
+
 pub fn create_virtualenv(
     output: CommandOutput,
     self_venv: &Path,
@@ -276,3 +502,41 @@ pub fn create_virtualenv(
     }
     Ok(())
 }
+
+#[test]
+fn test_dry_run_leaves_project_alone() {
+    let home = tempdir().unwrap();
+    env::set_var("RYE_HOME", home.path());
+    crate::platform::init().unwrap();
+
+    let dir = tempdir().unwrap();
+    let toml_path = dir.path().join("pyproject.toml");
+    fs::write(
+        &toml_path,
+        "[project]\nname = \"demo\"\nversion = \"0.1.0\"\nrequires-python = \">= 3.11\"\n",
+    )
+    .unwrap();
+    fs::write(dir.path().join("requirements.lock"), "click==8.1.3\n").unwrap();
+
+    // the toolchain is not installed, so the dry run has to stop before
+    // it would fetch it or create the virtualenv.
+    let rv = sync_project(
+        PyProject::load(&toml_path).unwrap(),
+        SyncOptions {
+            mode: SyncMode::LockOnly,
+            dry_run: true,
+            ..SyncOptions::default()
+        },
+    );
+    assert!(rv
+        .unwrap_err()
+        .to_string()
+        .contains("a dry run does not fetch it"));
+    assert!(!dir.path().join(".venv").exists());
+    assert!(!dir.path().join("requirements-dev.lock").exists());
+    assert_eq!(
+        fs::read_to_string(dir.path().join("requirements.lock")).unwrap(),
+        "click==8.1.3\n"
+    );
+    assert!(!home.path().join("py").exists());
+}